@@ -0,0 +1,49 @@
+//! Measures how long highlighting the input takes per keystroke on a long buffer, like a
+//! pasted script. Run with `cargo bench --bench highlight`.
+
+use jssh::highlight::Highlighter;
+use std::time::{Duration, Instant};
+
+const FUNCTION: &str = "function greet(name, times) {
+  for (let i = 0; i < times; i++) {
+    echo(`hello ${name} #${i}`); // say hi
+    cd(i % 2 ? '/tmp' : 'no/such/directory');
+  }
+}
+";
+
+const TYPED: &str = "echo(\"more\")";
+
+fn main() {
+    let source = FUNCTION.repeat(250);
+    let middle = source[..source.len() / 2].rfind('\n').unwrap() + 1;
+    println!("{} lines", source.lines().count());
+
+    for (place, position) in [("end", source.len()), ("middle", middle)] {
+        let mut highlighter = Highlighter::new();
+        let mut source = source.clone();
+
+        let start = Instant::now();
+        highlighter.highlight(&source, &[]);
+        let first = start.elapsed();
+
+        let mut total = Duration::ZERO;
+        let mut slowest = Duration::ZERO;
+        for (index, c) in TYPED.char_indices() {
+            source.insert(position + index, c);
+            let start = Instant::now();
+            // The shell looks up the names in the input before highlighting it.
+            highlighter.unresolved_globals(&source);
+            highlighter.highlight(&source, &[]);
+            let elapsed = start.elapsed();
+            total += elapsed;
+            slowest = slowest.max(elapsed);
+        }
+
+        let keystrokes = TYPED.chars().count() as u32;
+        println!(
+            "typing at the {place}: first highlight {first:?}, per keystroke {:?} on average and {slowest:?} at most",
+            total / keystrokes
+        );
+    }
+}
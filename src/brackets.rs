@@ -0,0 +1,218 @@
+const PAIRS: [(char, char); 6] = [
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('"', '"'),
+    ('\'', '\''),
+    ('`', '`'),
+];
+
+/// Returns the character that closes `c` if it opens a bracket or a string.
+pub fn closing(c: char) -> Option<char> {
+    PAIRS
+        .iter()
+        .find(|(open, _)| *open == c)
+        .map(|(_, close)| *close)
+}
+
+pub fn is_closing(c: char) -> bool {
+    PAIRS.iter().any(|(_, close)| *close == c)
+}
+
+fn is_quote(c: char) -> bool {
+    matches!(c, '"' | '\'' | '`')
+}
+
+enum State {
+    Code,
+    String(char),
+    LineComment,
+    BlockComment,
+}
+
+enum Frame {
+    Template,
+    /// A `${ }` inside a template literal and the number of `{` opened within it.
+    Interpolation(usize),
+}
+
+/// Walks `source` and calls `on_bracket` with the byte offset of every bracket (and comma)
+/// outside of strings and comments. Returns whether the end of `source` is outside of them as well.
+fn scan(source: &str, mut on_bracket: impl FnMut(usize, char)) -> bool {
+    let mut state = State::Code;
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        match state {
+            State::Code => match c {
+                '/' if next == Some('/') => state = State::LineComment,
+                '/' if next == Some('*') => {
+                    chars.next();
+                    state = State::BlockComment;
+                }
+                '`' => frames.push(Frame::Template),
+                '"' | '\'' => state = State::String(c),
+                '}' if matches!(frames.last(), Some(Frame::Interpolation(0))) => {
+                    frames.pop();
+                }
+                ',' => on_bracket(index, c),
+                '(' | '[' | '{' | ')' | ']' | '}' => {
+                    if let Some(Frame::Interpolation(depth)) = frames.last_mut() {
+                        match c {
+                            '{' => *depth += 1,
+                            '}' => *depth -= 1,
+                            _ => (),
+                        }
+                    }
+                    on_bracket(index, c)
+                }
+                _ => (),
+            },
+            State::String(quote) => match c {
+                '\\' => {
+                    chars.next();
+                }
+                '\n' => state = State::Code,
+                _ if c == quote => state = State::Code,
+                _ => (),
+            },
+            State::LineComment => {
+                if c == '\n' {
+                    state = State::Code
+                }
+            }
+            State::BlockComment => {
+                if c == '*' && next == Some('/') {
+                    chars.next();
+                    state = State::Code;
+                }
+            }
+        }
+
+        // Template literals are tracked on the frame stack so `${ }` can nest code in them.
+        if let (State::Code, Some(Frame::Template)) = (&state, frames.last()) {
+            let mut in_template = true;
+            while in_template {
+                let Some(&(_, c)) = chars.peek() else {
+                    break;
+                };
+                chars.next();
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '`' => {
+                        frames.pop();
+                        in_template = false;
+                    }
+                    '$' if chars.peek().map(|&(_, next)| next) == Some('{') => {
+                        chars.next();
+                        frames.push(Frame::Interpolation(0));
+                        in_template = false;
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    matches!(state, State::Code) && !matches!(frames.last(), Some(Frame::Template))
+}
+
+/// Calls `on_bracket` for every bracket and comma outside of strings and comments.
+pub fn for_each_bracket(source: &str, on_bracket: impl FnMut(usize, char)) {
+    scan(source, on_bracket);
+}
+
+/// Whether the end of `source` is outside of any string, template literal or comment.
+pub fn ends_in_code(source: &str) -> bool {
+    scan(source, |_, _| ())
+}
+
+pub struct Balance {
+    /// Byte offsets of every opening bracket and the bracket closing it.
+    pub pairs: Vec<(usize, usize)>,
+    /// Byte offsets of brackets without a partner.
+    pub unbalanced: Vec<usize>,
+}
+
+/// Matches up the brackets of `source`, ignoring those inside strings and comments.
+pub fn balance(source: &str) -> Balance {
+    let mut pairs = Vec::new();
+    let mut unbalanced = Vec::new();
+    let mut open: Vec<(usize, char)> = Vec::new();
+
+    scan(source, |index, c| match closing(c) {
+        _ if c == ',' => (),
+        Some(_) => open.push((index, c)),
+        None => match open.last() {
+            Some(&(start, opening)) if closing(opening) == Some(c) => {
+                open.pop();
+                pairs.push((start, index));
+            }
+            _ => unbalanced.push(index),
+        },
+    });
+
+    unbalanced.extend(open.iter().map(|&(index, _)| index));
+    Balance { pairs, unbalanced }
+}
+
+/// The innermost unclosed call around `position`: the offset of its `(` and the index of
+/// the argument `position` is in.
+pub fn enclosing_call(source: &str, position: usize) -> Option<(usize, usize)> {
+    let mut open: Vec<(usize, char, usize)> = Vec::new();
+
+    scan(&source[..position], |index, c| match closing(c) {
+        _ if c == ',' => {
+            if let Some((_, _, commas)) = open.last_mut() {
+                *commas += 1;
+            }
+        }
+        Some(_) => open.push((index, c, 0)),
+        None => {
+            open.pop();
+        }
+    });
+
+    open.iter()
+        .rev()
+        .find(|(_, c, _)| *c == '(')
+        .map(|&(index, _, commas)| (index, commas))
+}
+
+/// Returns the offsets of the bracket under `position` (or right before it) and its partner.
+pub fn matching(source: &str, position: usize) -> Option<(usize, usize)> {
+    let Balance { pairs, .. } = balance(source);
+    let before = source[..position]
+        .chars()
+        .next_back()
+        .map(|c| position - c.len_utf8());
+
+    [Some(position), before]
+        .into_iter()
+        .flatten()
+        .find_map(|index| {
+            pairs
+                .iter()
+                .find(|&&(start, end)| start == index || end == index)
+                .copied()
+        })
+}
+
+/// Whether typing the opening `c` at `position` should also insert its closing character.
+pub fn should_pair(source: &str, position: usize, c: char) -> bool {
+    let next = source[position..].chars().next();
+    let before = source[..position].chars().next_back();
+
+    let next_allows = match next {
+        None => true,
+        Some(next) => next.is_whitespace() || matches!(next, ')' | ']' | '}' | ';' | ','),
+    };
+    let before_allows = !is_quote(c)
+        || !matches!(before, Some(before) if before.is_alphanumeric() || before == '\\' || before == c);
+
+    next_allows && before_allows && ends_in_code(&source[..position])
+}
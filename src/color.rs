@@ -0,0 +1,124 @@
+use std::env;
+use syntect::highlighting::{Color, FontStyle, Style};
+
+/// The 16 colours of xterm's default palette, used to pick the closest one on terminals
+/// without 256 colours.
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Levels of each channel in the 6×6×6 colour cube of 256 colour terminals.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorSupport {
+    /// Goes by `COLORTERM` and the `TERM` the terminal describes itself with.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    /// The SGR parameters for drawing text in `style`: its foreground colour followed by
+    /// bold, italic and underline.
+    pub fn style(self, style: &Style) -> String {
+        let mut parameters = vec![self.foreground(style.foreground)];
+        for (font_style, parameter) in [
+            (FontStyle::BOLD, "1"),
+            (FontStyle::ITALIC, "3"),
+            (FontStyle::UNDERLINE, "4"),
+        ] {
+            if style.font_style.contains(font_style) {
+                parameters.push(parameter.to_string());
+            }
+        }
+        parameters.join(";")
+    }
+
+    /// Like themes for bat, an alpha of 0 makes the red channel the index of a colour in
+    /// the terminal's palette and an alpha of 1 stands for its default foreground.
+    fn foreground(self, Color { r, g, b, a }: Color) -> String {
+        match a {
+            0 => {
+                return match r {
+                    0..=7 => format!("{}", 30 + r),
+                    8..=15 => format!("{}", 90 + r - 8),
+                    _ => format!("38;5;{r}"),
+                }
+            }
+            1 => return String::from("39"),
+            _ => (),
+        }
+
+        match self {
+            ColorSupport::TrueColor => format!("38;2;{r};{g};{b}"),
+            ColorSupport::Ansi256 => format!("38;5;{}", ansi_256(r, g, b)),
+            ColorSupport::Ansi16 => match closest(&ANSI_16, (r, g, b)) {
+                index @ 0..=7 => format!("{}", 30 + index),
+                index => format!("{}", 90 + index - 8),
+            },
+        }
+    }
+}
+
+/// The closest colour of the 256 colour palette, from either the colour cube or the
+/// grey ramp.
+fn ansi_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |channel: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&index| CUBE_LEVELS[index].abs_diff(channel))
+            .unwrap_or(0)
+    };
+    let (red, green, blue) = (level(r), level(g), level(b));
+    let cube = (CUBE_LEVELS[red], CUBE_LEVELS[green], CUBE_LEVELS[blue]);
+
+    let average = (r as usize + g as usize + b as usize) / 3;
+    let grey_index = (average.saturating_sub(3) / 10).min(23);
+    let grey_level = (8 + grey_index * 10) as u8;
+    let grey = (grey_level, grey_level, grey_level);
+
+    match distance(grey, (r, g, b)) < distance(cube, (r, g, b)) {
+        true => 232 + grey_index as u8,
+        false => 16 + (36 * red + 6 * green + blue) as u8,
+    }
+}
+
+/// Index of the colour in `palette` closest to `color`.
+fn closest(palette: &[(u8, u8, u8)], color: (u8, u8, u8)) -> usize {
+    (0..palette.len())
+        .min_by_key(|&index| distance(palette[index], color))
+        .unwrap_or(0)
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel(r1, r2) + channel(g1, g2) + channel(b1, b2)
+}
@@ -1,6 +1,9 @@
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{history::HistorySearch, keymap::Action};
 
 lazy_static! {
     pub static ref CONFIG: Mutex<Config> =
@@ -8,9 +11,36 @@ lazy_static! {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
     pub start_up_file: String,
     pub history_file: String,
+    pub history_search: HistorySearch,
+    /// Whether commands run in other sessions show up in the history right away.
+    pub share_history: bool,
+    /// Maximum number of history entries, 0 for no limit.
+    pub history_size: usize,
+    /// Whether running a command again removes its older history entries.
+    pub history_erase_duplicates: bool,
+    /// Whether inputs starting with a space are left out of the history.
+    pub history_ignore_space: bool,
+    /// Regular expressions of inputs left out of the history.
+    pub history_ignore: Vec<String>,
+    /// Whether likely secrets are replaced with `<redacted>` in the history file.
+    pub history_redact_secrets: bool,
+    /// Name of a builtin highlighting theme or a path to a `.tmTheme` or JSON theme.
+    pub theme: String,
+    /// Whether signature hints for executables run them with `--help`. Off by default, as
+    /// it runs whatever is being typed before it's submitted, and programs that don't
+    /// handle `--help` do their actual work instead.
+    pub executable_help: bool,
+    /// Whether submitted inputs are left with the compact `$transientPrompt`.
+    pub transient_prompt: bool,
+    /// Commands taking at least this many milliseconds are followed by how long they took,
+    /// 0 turns it off.
+    pub slow_command_threshold: u64,
+    /// Extra key bindings from key sequences like `"ctrl-x ctrl-k"` to editor actions.
+    pub keymap: BTreeMap<String, Action>,
 }
 
 impl Default for Config {
@@ -18,6 +48,18 @@ impl Default for Config {
         Self {
             start_up_file: String::from("~/.jssh.js"),
             history_file: String::from("~/.jssh_history"),
+            history_search: HistorySearch::Prefix,
+            share_history: false,
+            history_size: 10000,
+            history_erase_duplicates: false,
+            history_ignore_space: true,
+            history_ignore: Vec::new(),
+            history_redact_secrets: true,
+            theme: String::from("default"),
+            executable_help: false,
+            transient_prompt: false,
+            slow_command_threshold: 0,
+            keymap: BTreeMap::new(),
         }
     }
 }
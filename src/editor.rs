@@ -0,0 +1,543 @@
+use anyhow::{bail, Context};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process::Command,
+};
+
+use crate::{
+    brackets,
+    config::CONFIG,
+    highlight::{Mark, MATCH, MATCHING_BRACKET},
+    history::{find_entry, next_word, suggest, Direction, HISTORY, HISTORY_POINTER},
+    keymap::Action,
+    terminal,
+};
+
+lazy_static! {
+    /// Text to start the next prompt with instead of an empty buffer, set by `$edit`.
+    pub static ref PENDING_INPUT: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Opens `text` in `$VISUAL` or `$EDITOR` as a temporary `.js` file and returns what was
+/// saved, without the trailing newline editors like to add.
+pub fn edit_externally(text: &str) -> anyhow::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut words = editor.split_whitespace();
+    let Some(program) = words.next() else {
+        bail!("$VISUAL/$EDITOR is empty");
+    };
+
+    let (path, mut file) = create_temporary_file()?;
+    file.write_all(text.as_bytes())?;
+    drop(file);
+
+    // The editor gets the terminal as it was before jssh set it up, without raw mode,
+    // keyboard enhancements or bracketed paste.
+    terminal::de_init()?;
+    let status = Command::new(program).args(words).arg(&path).status();
+    terminal::init()?;
+
+    let status = status.with_context(|| format!("Could not start `{editor}`"))?;
+    let edited = fs::read_to_string(&path);
+    fs::remove_file(&path)?;
+
+    if !status.success() {
+        bail!("`{editor}` exited with {status}");
+    }
+    Ok(edited?.trim_end_matches('\n').to_string())
+}
+
+/// Creates a new `.js` file in the temp directory that only the user can read. Its name
+/// can't be guessed, so nobody can put a symlink in its place beforehand.
+fn create_temporary_file() -> io::Result<(PathBuf, File)> {
+    loop {
+        let random = RandomState::new().build_hasher().finish();
+        let name = format!("jssh-{}-{random:016x}.js", std::process::id());
+        let path = env::temp_dir().join(name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path);
+        match file {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Converts a JavaScript string index into a byte offset of `text`, clamped to its length.
+pub fn byte_offset(text: &str, utf16_offset: usize) -> usize {
+    let mut units = 0;
+    text.char_indices()
+        .find(|(_, c)| {
+            units += c.len_utf16();
+            units > utf16_offset
+        })
+        .map_or(text.len(), |(index, _)| index)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// A state of the buffer to go back to with undo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    buffer: String,
+    cursor: usize,
+}
+
+/// Kinds of edits. Consecutive edits of the same kind are undone together, except for
+/// [`Group::Other`] which always gets its own undo step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Insert,
+    Delete,
+    History,
+    Other,
+}
+
+/// The input buffer of the line editor and the cursor inside it.
+#[derive(Default)]
+pub struct Editor {
+    pub buffer: String,
+    /// Byte offset of the cursor in `buffer`, always on a char boundary.
+    pub cursor: usize,
+    /// What was typed before browsing the history, restored after the newest entry.
+    typed: String,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// The group the last edit belonged to, `None` after anything that ends a group.
+    group: Option<Group>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn perform(&mut self, action: Action) -> anyhow::Result<()> {
+        let group = match action {
+            Action::Undo => {
+                self.undo();
+                return Ok(());
+            }
+            Action::Redo => {
+                self.redo();
+                return Ok(());
+            }
+            Action::BackwardDeleteChar | Action::DeleteChar => Group::Delete,
+            Action::HistoryPrevious | Action::HistoryNext => Group::History,
+            _ => Group::Other,
+        };
+        self.track(group, |editor| editor.apply(action))
+    }
+
+    fn apply(&mut self, action: Action) -> anyhow::Result<()> {
+        match action {
+            // Submitting and cancelling end the line and are handled by the caller.
+            Action::AcceptLine | Action::Cancel | Action::Undo | Action::Redo => (),
+            Action::InsertNewline => self.insert_str("\n"),
+            Action::HistoryPrevious => self.history(Direction::Older),
+            Action::HistoryNext => self.history(Direction::Newer),
+            Action::BackwardChar => self.cursor = self.previous_boundary(),
+            Action::ForwardChar => {
+                if !self.accept_suggestion(false) {
+                    self.cursor = self.next_boundary();
+                }
+            }
+            Action::BackwardWord => self.cursor = self.previous_word(),
+            Action::ForwardWord => {
+                if !self.accept_suggestion(true) {
+                    self.cursor = self.next_word();
+                }
+            }
+            Action::BeginningOfLine => self.cursor = 0,
+            Action::EndOfLine => {
+                if !self.accept_suggestion(false) {
+                    self.cursor = self.buffer.len();
+                }
+            }
+            Action::BackwardDeleteChar => self.backward_delete_char(),
+            Action::DeleteChar => self.delete(self.cursor..self.next_boundary()),
+            Action::BackwardKillWord => self.delete(self.previous_word()..self.cursor),
+            Action::BackwardKillLine => self.delete(0..self.cursor),
+            Action::KillLine => self.delete(self.cursor..self.buffer.len()),
+            Action::EditExternally => {
+                let edited = edit_externally(&self.buffer)?;
+                self.buffer = edited.replace("\r\n", "\n");
+                self.cursor = self.buffer.len();
+                self.edited();
+            }
+        }
+        Ok(())
+    }
+
+    /// Types `c` at the cursor, closing brackets and quotes or stepping over a closing one.
+    /// Typing a word goes into a single undo step together with the separator after it.
+    pub fn insert(&mut self, c: char) {
+        self.track(Group::Insert, |editor| {
+            editor.insert_char(c);
+            Ok(())
+        })
+        .unwrap();
+        if !is_word(c) {
+            self.group = None;
+        }
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let next = self.buffer[self.cursor..].chars().next();
+        if brackets::is_closing(c) && next == Some(c) {
+            self.cursor += c.len_utf8();
+            return;
+        }
+
+        let close =
+            brackets::closing(c).filter(|_| brackets::should_pair(&self.buffer, self.cursor, c));
+        self.buffer.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        if let Some(close) = close {
+            self.buffer.insert(self.cursor, close);
+        }
+        self.edited();
+    }
+
+    /// Inserts pasted text at the cursor as is, newlines included.
+    pub fn insert_str(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        self.track(Group::Other, |editor| {
+            editor.buffer.insert_str(editor.cursor, &text);
+            editor.cursor += text.len();
+            editor.edited();
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    /// Replaces the whole buffer, e.g. with the result of a key binding. The cursor is a
+    /// JavaScript string index and gets clamped to the new buffer.
+    pub fn replace(&mut self, buffer: &str, utf16_cursor: usize) {
+        let before = self.snapshot();
+        self.buffer = buffer.to_string();
+        self.cursor = byte_offset(&self.buffer, utf16_cursor);
+        self.edited();
+        self.record(before, Group::Other);
+    }
+
+    /// The cursor as a JavaScript string index.
+    pub fn utf16_cursor(&self) -> usize {
+        self.buffer[..self.cursor].encode_utf16().count()
+    }
+
+    /// Returns the history suggestion to draw after the cursor, if any.
+    pub fn suggestion(&self) -> Option<String> {
+        if self.cursor != self.buffer.len() || *HISTORY_POINTER.lock() != 0 {
+            return None;
+        }
+        suggest(&HISTORY.lock(), &self.buffer)
+    }
+
+    /// Styles the part of a history entry matching the typed text and the bracket pair
+    /// around the cursor.
+    pub fn marks(&self) -> Vec<Mark> {
+        let mut marks = match *HISTORY_POINTER.lock() {
+            0 => vec![],
+            _ => CONFIG
+                .lock()
+                .history_search
+                .find(&self.buffer, &self.typed)
+                .filter(|range| !range.is_empty())
+                .map(|range| Mark {
+                    range,
+                    escape: MATCH,
+                })
+                .into_iter()
+                .collect(),
+        };
+
+        let matching = brackets::matching(&self.buffer, self.cursor)
+            .into_iter()
+            .flat_map(|(start, end)| [start, end])
+            .map(|index| Mark {
+                range: index..index + 1,
+                escape: MATCHING_BRACKET,
+            });
+        marks.extend(matching);
+        marks
+    }
+
+    fn accept_suggestion(&mut self, word: bool) -> bool {
+        let Some(suggestion) = self.suggestion() else {
+            return false;
+        };
+        let accepted = match word {
+            true => &suggestion[..next_word(&suggestion)],
+            false => &suggestion,
+        };
+        self.buffer.push_str(accepted);
+        self.cursor = self.buffer.len();
+        true
+    }
+
+    fn history(&mut self, direction: Direction) {
+        // Multiline input is walked line by line before moving on to the history.
+        if *HISTORY_POINTER.lock() == 0 && self.move_line(&direction) {
+            return;
+        }
+
+        let history = HISTORY.lock();
+        let pointer = *HISTORY_POINTER.lock();
+
+        let next = match direction {
+            Direction::Older => {
+                if pointer == 0 {
+                    self.typed = self.buffer.clone();
+                }
+                find_entry(&history, pointer, &self.typed, &self.buffer, direction)
+            }
+            Direction::Newer if pointer == 0 => return,
+            Direction::Newer => {
+                find_entry(&history, pointer, &self.typed, &self.buffer, direction).or(Some(0))
+            }
+        };
+        let Some(next) = next else {
+            return;
+        };
+
+        *HISTORY_POINTER.lock() = next;
+        self.buffer = match next {
+            0 => self.typed.clone(),
+            _ => history[history.len() - next].command.clone(),
+        };
+
+        // Keep the cursor where it was, as long as the new entry is long enough.
+        self.cursor = self.cursor.min(self.buffer.len());
+        while !self.buffer.is_char_boundary(self.cursor) {
+            self.cursor -= 1;
+        }
+    }
+
+    /// Moves the cursor to the same column of the line above or below, if there is one.
+    fn move_line(&mut self, direction: &Direction) -> bool {
+        let line_start = self.buffer[..self.cursor]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let column = self.buffer[line_start..self.cursor].chars().count();
+
+        let target_start = match direction {
+            Direction::Older => match line_start {
+                0 => return false,
+                _ => self.buffer[..line_start - 1]
+                    .rfind('\n')
+                    .map_or(0, |index| index + 1),
+            },
+            Direction::Newer => match self.buffer[self.cursor..].find('\n') {
+                Some(index) => self.cursor + index + 1,
+                None => return false,
+            },
+        };
+
+        let line = self.buffer[target_start..].split('\n').next().unwrap_or("");
+        self.cursor = target_start
+            + line
+                .char_indices()
+                .nth(column)
+                .map_or(line.len(), |(index, _)| index);
+        true
+    }
+
+    fn backward_delete_char(&mut self) {
+        let start = self.previous_boundary();
+        let Some(c) = self.buffer[start..self.cursor].chars().next_back() else {
+            self.edited();
+            return;
+        };
+
+        // Deleting an opening bracket right before its closing one removes both.
+        let next = self.buffer[self.cursor..].chars().next();
+        let end = match next {
+            Some(next) if brackets::closing(c) == Some(next) => self.cursor + next.len_utf8(),
+            _ => self.cursor,
+        };
+        self.delete(start..end);
+    }
+
+    fn delete(&mut self, range: std::ops::Range<usize>) {
+        self.buffer.replace_range(range.clone(), "");
+        if self.cursor > range.start {
+            self.cursor = range.start.max(self.cursor.saturating_sub(range.len()));
+        }
+        self.edited();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            buffer: self.buffer.clone(),
+            cursor: self.cursor,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.buffer = snapshot.buffer;
+        self.cursor = snapshot.cursor;
+        self.group = None;
+        self.edited();
+    }
+
+    /// Runs `edit` and adds the state from before it to the undo stack, unless it continues
+    /// the group of the previous edit.
+    fn track(
+        &mut self,
+        group: Group,
+        edit: impl FnOnce(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let before = self.snapshot();
+        let result = edit(self);
+        self.record(before, group);
+        result
+    }
+
+    fn record(&mut self, before: Snapshot, group: Group) {
+        // Only moving the cursor ends the current group without adding an undo step.
+        if self.buffer == before.buffer {
+            self.group = None;
+            return;
+        }
+
+        if group == Group::Other || self.group != Some(group) {
+            self.undo.push(before);
+            self.redo.clear();
+        }
+        self.group = Some(group);
+    }
+
+    fn undo(&mut self) {
+        if let Some(snapshot) = self.undo.pop() {
+            self.redo.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.redo.pop() {
+            self.undo.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    /// Any edit ends browsing the history, so the next Up searches for the new text.
+    fn edited(&mut self) {
+        *HISTORY_POINTER.lock() = 0;
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.buffer[..self.cursor]
+            .chars()
+            .next_back()
+            .map_or(self.cursor, |c| self.cursor - c.len_utf8())
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.buffer[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    fn previous_word(&self) -> usize {
+        let before = &self.buffer[..self.cursor];
+        let end = before.trim_end_matches(|c: char| !is_word(c)).len();
+        before[..end].trim_end_matches(is_word).len()
+    }
+
+    fn next_word(&self) -> usize {
+        self.cursor + next_word(&self.buffer[self.cursor..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> Editor {
+        let mut editor = Editor::new();
+        for c in text.chars() {
+            editor.insert(c);
+        }
+        editor
+    }
+
+    fn backspace(editor: &mut Editor) {
+        editor.perform(Action::BackwardDeleteChar).unwrap();
+    }
+
+    #[test]
+    fn pairs_brackets_and_quotes() {
+        let editor = typed("f(");
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("f()", 2));
+
+        let editor = typed("f(\"a");
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("f(\"a\")", 4));
+
+        // Typing the closing character steps over it.
+        let editor = typed("f(x)");
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("f(x)", 4));
+    }
+
+    #[test]
+    fn does_not_pair_inside_strings() {
+        let editor = typed("\"a(");
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("\"a(\"", 3));
+    }
+
+    #[test]
+    fn deletes_empty_pairs_together() {
+        let mut editor = typed("f(");
+        backspace(&mut editor);
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("f", 1));
+
+        let mut editor = typed("\"");
+        backspace(&mut editor);
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("", 0));
+    }
+
+    #[test]
+    fn deletes_one_side_of_filled_pairs() {
+        let mut editor = typed("f(x");
+        backspace(&mut editor);
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("f()", 2));
+
+        let mut editor = typed("f(x");
+        editor.cursor = 2;
+        backspace(&mut editor);
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("fx)", 1));
+    }
+
+    #[test]
+    fn deletes_single_characters_inside_strings() {
+        let mut editor = typed("\"a(");
+        backspace(&mut editor);
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("\"a\"", 2));
+    }
+
+    #[test]
+    fn backspace_at_the_start_deletes_nothing() {
+        let mut editor = typed("\"");
+        editor.cursor = 0;
+        backspace(&mut editor);
+        assert_eq!((editor.buffer.as_str(), editor.cursor), ("\"\"", 0));
+    }
+}
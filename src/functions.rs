@@ -1,37 +1,33 @@
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use is_executable::IsExecutable;
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use regex::Regex;
 use rusty_v8::{
-    self as v8, FunctionCallback, FunctionTemplate, HandleScope, Local, MapFnTo, Object,
+    self as v8, Function, FunctionCallback, FunctionTemplate, HandleScope, Local, MapFnTo, Object,
+    SideEffectType,
 };
-use std::{env, path::Path, process::Command};
+use std::{env, os::unix::process::ExitStatusExt, path::Path, process::Command};
 
 use crate::{
-    config::CONFIG,
+    editor::{edit_externally, PENDING_INPUT},
+    git,
+    history::{self, Entry, Filter, HISTORY},
     io::flush,
+    keymap::{parse_sequence, Action, Binding, KeyCallbacks, KEYMAP},
     path::{expand, expand_path},
+    shell_history,
+    terminal::{disable_raw_mode, enable_raw_mode},
+    theme,
 };
 
 lazy_static! {
     pub static ref RUNNING: Mutex<bool> = Mutex::new(true);
-    pub static ref HISTORY: Mutex<Vec<String>> = {
-        let history_file_path = expand_path(&(*CONFIG.lock().history_file.clone()));
-        let history = if history_file_path.exists() {
-            let history_file = std::fs::read_to_string(history_file_path).unwrap();
-            history_file
-                .split("\n")
-                .map(|entry| String::from(entry))
-                .collect::<Vec<String>>()
-        } else {
-            vec![]
-        };
-
-        Mutex::new(history)
-    };
-    pub static ref HISTORY_POINTER: Mutex<usize> = Mutex::new(0);
+    /// Exit status of the last executable `$run` ran, or 128 plus the signal that killed it.
+    pub static ref EXIT_STATUS: Mutex<i32> = Mutex::new(0);
+    /// Ids of the history entries `$history.rerun` is running, so an entry rerunning
+    /// itself throws instead of recursing.
+    static ref RERUNNING: Mutex<Vec<u64>> = Mutex::new(Vec::new());
     pub static ref EXECUTABLES: Mutex<Vec<String>> = {
         let mut paths = env::var_os("PATH")
             .unwrap()
@@ -71,6 +67,122 @@ lazy_static! {
     };
 }
 
+pub struct BuiltinSignature {
+    pub name: &'static str,
+    /// Comma separated parameters, optional ones end in `?`.
+    pub parameters: &'static str,
+    pub description: &'static str,
+}
+
+/// Signatures of the functions from `create_functions`, shown while typing calls to them.
+pub const BUILTIN_SIGNATURES: [BuiltinSignature; 21] = [
+    BuiltinSignature {
+        name: "$exit",
+        parameters: "",
+        description: "Exits the shell",
+    },
+    BuiltinSignature {
+        name: "$find",
+        parameters: "pattern",
+        description: "Lists executables fuzzy matching the pattern",
+    },
+    BuiltinSignature {
+        name: "$setEnv",
+        parameters: "key, value",
+        description: "Sets an environment variable",
+    },
+    BuiltinSignature {
+        name: "$getEnv",
+        parameters: "key",
+        description: "Returns an environment variable",
+    },
+    BuiltinSignature {
+        name: "$history",
+        parameters: "",
+        description: "Returns the history as an array of commands",
+    },
+    BuiltinSignature {
+        name: "$history.entries",
+        parameters: "",
+        description: "Returns the history entries with their id, start, duration, cwd and status",
+    },
+    BuiltinSignature {
+        name: "$history.search",
+        parameters: "query, { cwd, since, failed }?",
+        description: "Returns the history entries containing the query",
+    },
+    BuiltinSignature {
+        name: "$history.delete",
+        parameters: "id",
+        description: "Deletes a history entry, also from the history file",
+    },
+    BuiltinSignature {
+        name: "$history.clear",
+        parameters: "",
+        description: "Deletes every history entry, also from the history file",
+    },
+    BuiltinSignature {
+        name: "$history.import",
+        parameters: "path, format",
+        description: "Adds the entries of a bash, zsh or fish history file to the history",
+    },
+    BuiltinSignature {
+        name: "$history.export",
+        parameters: "path, format",
+        description: "Writes the history to a file in bash, zsh or fish format",
+    },
+    BuiltinSignature {
+        name: "$history.rerun",
+        parameters: "id",
+        description: "Runs a history entry again and returns its result",
+    },
+    BuiltinSignature {
+        name: "$edit",
+        parameters: "id?",
+        description: "Edits the last or the given history entry in $EDITOR",
+    },
+    BuiltinSignature {
+        name: "$bindKey",
+        parameters: "keys, action",
+        description: "Binds a key sequence to an editor action or a function",
+    },
+    BuiltinSignature {
+        name: "$source",
+        parameters: "file",
+        description: "Runs a JavaScript file",
+    },
+    BuiltinSignature {
+        name: "$run",
+        parameters: "command, ...args",
+        description: "Runs an executable",
+    },
+    BuiltinSignature {
+        name: "$drop",
+        parameters: "...names",
+        description: "Deletes global variables",
+    },
+    BuiltinSignature {
+        name: "$git.status",
+        parameters: "",
+        description: "Returns the branch, ahead/behind counts and changes of the repository",
+    },
+    BuiltinSignature {
+        name: "$theme.list",
+        parameters: "",
+        description: "Returns the names of the builtin highlighting themes",
+    },
+    BuiltinSignature {
+        name: "$theme.set",
+        parameters: "name",
+        description: "Switches to a builtin theme or a .tmTheme or JSON theme file",
+    },
+    BuiltinSignature {
+        name: "cd",
+        parameters: "path?",
+        description: "Changes the working directory",
+    },
+];
+
 pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
     create_js_function(
         scope,
@@ -139,7 +251,7 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
         },
     );
 
-    create_js_function(
+    create_pure_js_function(
         scope,
         global,
         "$getEnv",
@@ -164,7 +276,7 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
         },
     );
 
-    create_js_function(
+    let history = create_pure_js_function(
         scope,
         global,
         "$history",
@@ -177,7 +289,7 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
                 .iter()
                 .enumerate()
                 .for_each(|(index, entry)| {
-                    let value = v8::String::new(scope, &entry).unwrap().into();
+                    let value = v8::String::new(scope, &entry.command).unwrap().into();
                     return_array.set_index(scope, index as u32, value);
                 });
 
@@ -185,6 +297,243 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
         },
     );
 
+    create_pure_js_function(
+        scope,
+        history.into(),
+        "entries",
+        |scope: &mut v8::HandleScope,
+         _args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let history = HISTORY.lock();
+            let entries = history
+                .iter()
+                .map(|entry| entry_object(scope, entry).into())
+                .collect::<Vec<Local<v8::Value>>>();
+            rv.set(v8::Array::new_with_elements(scope, &entries).into())
+        },
+    );
+
+    create_pure_js_function(
+        scope,
+        history.into(),
+        "search",
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let query = args.get(0);
+            if !query.is_undefined() && !query.is_string() {
+                throw_type_error(scope, "$history.search expects a string to search for");
+                return;
+            }
+            let query = match query.is_undefined() {
+                true => String::new(),
+                false => query.to_rust_string_lossy(scope),
+            };
+            let Some(filter) = history_filter(scope, args.get(1)) else {
+                return;
+            };
+
+            let history = HISTORY.lock();
+            let entries = history::search(&history, &query, &filter)
+                .into_iter()
+                .map(|index| entry_object(scope, &history[index]).into())
+                .collect::<Vec<Local<v8::Value>>>();
+            rv.set(v8::Array::new_with_elements(scope, &entries).into())
+        },
+    );
+
+    create_js_function(
+        scope,
+        history.into(),
+        "delete",
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let Some(id) = history_id(scope, args.get(0), "$history.delete") else {
+                return;
+            };
+            match history::delete(id) {
+                Ok(Some(entry)) => rv.set(entry_object(scope, &entry).into()),
+                Ok(None) => throw_error(scope, "$history.delete: no such history entry"),
+                Err(error) => throw_error(scope, &format!("$history.delete: {error}")),
+            }
+        },
+    );
+
+    create_js_function(
+        scope,
+        history.into(),
+        "clear",
+        |scope: &mut v8::HandleScope,
+         _args: v8::FunctionCallbackArguments,
+         _rv: v8::ReturnValue| {
+            if let Err(error) = history::clear() {
+                throw_error(scope, &format!("$history.clear: {error}"));
+            }
+        },
+    );
+
+    create_js_function(
+        scope,
+        history.into(),
+        "import",
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let Some((path, format)) = history_file_arguments(scope, &args, "$history.import")
+            else {
+                return;
+            };
+            let imported = std::fs::read(expand_path(&path))
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| history::merge(shell_history::parse(format, &contents)));
+            match imported {
+                Ok(count) => rv.set(v8::Number::new(scope, count as f64).into()),
+                Err(error) => throw_error(scope, &format!("$history.import: {error}")),
+            }
+        },
+    );
+
+    create_js_function(
+        scope,
+        history.into(),
+        "export",
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let Some((path, format)) = history_file_arguments(scope, &args, "$history.export")
+            else {
+                return;
+            };
+            let entries = HISTORY
+                .lock()
+                .iter()
+                .filter(|entry| entry.status.is_some() || entry.session.is_none())
+                .map(Entry::persisted)
+                .collect::<Vec<Entry>>();
+            match std::fs::write(expand_path(&path), shell_history::format(format, &entries)) {
+                Ok(()) => rv.set(v8::Number::new(scope, entries.len() as f64).into()),
+                Err(error) => throw_error(scope, &format!("$history.export: {error}")),
+            }
+        },
+    );
+
+    create_js_function(
+        scope,
+        history.into(),
+        "rerun",
+        |scope: &mut v8::HandleScope,
+         args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let Some(id) = history_id(scope, args.get(0), "$history.rerun") else {
+                return;
+            };
+            let command = HISTORY
+                .lock()
+                .iter()
+                .find(|entry| entry.id == id)
+                .map(|entry| entry.command.clone());
+            let Some(command) = command else {
+                throw_error(scope, "$history.rerun: no such history entry");
+                return;
+            };
+            if RERUNNING.lock().contains(&id) {
+                throw_error(scope, "$history.rerun: the entry is already running");
+                return;
+            }
+
+            let code = v8::String::new(scope, &command).unwrap();
+            let Some(script) = v8::Script::compile(scope, code, None) else {
+                return;
+            };
+            RERUNNING.lock().push(id);
+            let result = script.run(scope);
+            RERUNNING.lock().pop();
+            if let Some(result) = result {
+                rv.set(result);
+            }
+        },
+    );
+
+    create_js_function(
+        scope,
+        global,
+        "$edit",
+        |scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _rv: v8::ReturnValue| {
+            let id = args.get(0);
+            let id = match id.is_undefined() {
+                true => history::before_running(&HISTORY.lock(), 1).map(|entry| entry.id),
+                false => match history_id(scope, id, "$edit") {
+                    Some(id) => Some(id),
+                    None => return,
+                },
+            };
+            let entry = id.and_then(|id| {
+                HISTORY
+                    .lock()
+                    .iter()
+                    .find(|entry| entry.id == id)
+                    .map(|entry| entry.command.clone())
+            });
+            let Some(entry) = entry else {
+                throw_error(scope, "$edit: no such history entry");
+                return;
+            };
+
+            match edit_externally(&entry) {
+                Ok(edited) => *PENDING_INPUT.lock() = Some(edited),
+                Err(error) => throw_error(scope, &error.to_string()),
+            }
+        },
+    );
+
+    create_js_function(
+        scope,
+        global,
+        "$bindKey",
+        |scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _rv: v8::ReturnValue| {
+            let keys = args.get(0);
+            let target = args.get(1);
+            if !keys.is_string() || !(target.is_string() || target.is_function()) {
+                throw_type_error(
+                    scope,
+                    "$bindKey expects a key sequence and an action name or a function",
+                );
+                return;
+            }
+
+            let keys = keys.to_rust_string_lossy(scope);
+            let sequence = match parse_sequence(&keys) {
+                Ok(sequence) => sequence,
+                Err(error) => {
+                    throw_type_error(scope, &format!("Invalid key sequence `{keys}`: {error}"));
+                    return;
+                }
+            };
+
+            let binding = if let Ok(function) = v8::Local::<v8::Function>::try_from(target) {
+                let function = v8::Global::new(scope, function);
+                if scope.get_slot::<KeyCallbacks>().is_none() {
+                    scope.set_slot(KeyCallbacks::default());
+                }
+                let callbacks = scope.get_slot_mut::<KeyCallbacks>().unwrap();
+                callbacks.0.push(function);
+                Binding::Function(callbacks.0.len() - 1)
+            } else {
+                let name = target.to_rust_string_lossy(scope);
+                match name.parse::<Action>() {
+                    Ok(action) => Binding::Action(action),
+                    Err(_error) => {
+                        throw_type_error(scope, &format!("Unknown editor action `{name}`"));
+                        return;
+                    }
+                }
+            };
+
+            KEYMAP.lock().bind(sequence, binding);
+        },
+    );
+
     create_js_function(
         scope,
         global,
@@ -248,7 +597,10 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
             disable_raw_mode().unwrap();
             let mut child = command.spawn().unwrap();
 
-            child.wait().unwrap();
+            let status = child.wait().unwrap();
+            *EXIT_STATUS.lock() = status
+                .code()
+                .unwrap_or_else(|| 128 + status.signal().unwrap_or(0));
             enable_raw_mode().unwrap();
 
             flush()
@@ -329,6 +681,80 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
     //     global.set(scope, key.into(), value.into());
     // });
 
+    let theme = v8::Object::new(scope);
+    create_pure_js_function(
+        scope,
+        theme,
+        "list",
+        |scope: &mut v8::HandleScope,
+         _args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let names = theme::list()
+                .iter()
+                .map(|name| v8::String::new(scope, name).unwrap().into())
+                .collect::<Vec<Local<v8::Value>>>();
+            rv.set(v8::Array::new_with_elements(scope, &names).into())
+        },
+    );
+    create_js_function(
+        scope,
+        theme,
+        "set",
+        |scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _rv: v8::ReturnValue| {
+            let name = args.get(0);
+            if !name.is_string() {
+                throw_type_error(scope, "$theme.set expects the name of a theme or a path");
+                return;
+            }
+            if let Err(error) = theme::set(&name.to_rust_string_lossy(scope)) {
+                throw_error(scope, &format!("$theme.set: {error}"));
+            }
+        },
+    );
+    let name = v8::String::new(scope, "$theme").unwrap();
+    global.set(scope, name.into(), theme.into());
+
+    let git = v8::Object::new(scope);
+    create_js_function(
+        scope,
+        git,
+        "status",
+        |scope: &mut v8::HandleScope,
+         _args: v8::FunctionCallbackArguments,
+         mut rv: v8::ReturnValue| {
+            let Some(status) = env::current_dir().ok().and_then(|cwd| git::status(&cwd)) else {
+                rv.set(v8::null(scope).into());
+                return;
+            };
+
+            let object = v8::Object::new(scope);
+            let branch = match &status.branch {
+                Some(branch) => v8::String::new(scope, branch).unwrap().into(),
+                None => v8::null(scope).into(),
+            };
+            set_property(scope, object, "branch", branch);
+            for (key, count) in [
+                ("ahead", status.ahead),
+                ("behind", status.behind),
+                ("staged", status.staged),
+                ("modified", status.modified),
+                ("untracked", status.untracked),
+                ("conflicted", status.conflicted),
+            ] {
+                let count = v8::Number::new(scope, count as f64).into();
+                set_property(scope, object, key, count);
+            }
+            let dirty = v8::Boolean::new(scope, status.is_dirty()).into();
+            set_property(scope, object, "dirty", dirty);
+            let pending = v8::Boolean::new(scope, status.pending).into();
+            set_property(scope, object, "pending", pending);
+
+            rv.set(object.into());
+        },
+    );
+    let name = v8::String::new(scope, "$git").unwrap();
+    global.set(scope, name.into(), git.into());
+
     /*
      * `cd` is a shell builtin and should be defined after all other executables
      */
@@ -360,15 +786,189 @@ pub fn create_functions(scope: &mut HandleScope, global: Local<Object>) {
     );
 }
 
-pub fn create_js_function(
+/// Builds the object `$history.entries()` and `$history.search()` describe an entry with.
+fn entry_object<'s>(scope: &mut HandleScope<'s>, entry: &Entry) -> Local<'s, Object> {
+    let object = v8::Object::new(scope);
+
+    let value = v8::Number::new(scope, entry.id as f64).into();
+    set_property(scope, object, "id", value);
+    let value = v8::String::new(scope, &entry.command).unwrap().into();
+    set_property(scope, object, "command", value);
+    let value = match entry.start {
+        Some(start) => v8::Date::new(scope, start as f64 * 1000.0).unwrap().into(),
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, object, "start", value);
+    let value = match entry.duration {
+        Some(duration) => v8::Number::new(scope, duration as f64).into(),
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, object, "duration", value);
+    let value = match &entry.cwd {
+        Some(cwd) => v8::String::new(scope, &cwd.to_string_lossy())
+            .unwrap()
+            .into(),
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, object, "cwd", value);
+    let value = match entry.status {
+        Some(status) => v8::Integer::new(scope, status).into(),
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, object, "status", value);
+    let value = v8::Boolean::new(scope, entry.failed()).into();
+    set_property(scope, object, "failed", value);
+    let value = match &entry.session {
+        Some(session) => v8::String::new(scope, session).unwrap().into(),
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, object, "session", value);
+
+    object
+}
+
+/// Reads the `{ cwd, since, failed }` options of `$history.search`, throwing if they
+/// have the wrong type.
+fn history_filter(scope: &mut HandleScope, options: Local<v8::Value>) -> Option<Filter> {
+    if options.is_undefined() {
+        return Some(Filter::default());
+    }
+    let Some(options) = options.to_object(scope).filter(|_| options.is_object()) else {
+        throw_type_error(scope, "$history.search expects its options as an object");
+        return None;
+    };
+
+    let mut filter = Filter::default();
+    let key = v8::String::new(scope, "cwd").unwrap().into();
+    let cwd = options.get(scope, key)?;
+    if cwd.is_string() {
+        let cwd = expand_path(&cwd.to_rust_string_lossy(scope));
+        filter.cwd = Some(env::current_dir().map_or(cwd.clone(), |current| current.join(cwd)));
+    } else if !cwd.is_undefined() {
+        throw_type_error(scope, "$history.search expects `cwd` to be a path");
+        return None;
+    }
+
+    let key = v8::String::new(scope, "since").unwrap().into();
+    let since = options.get(scope, key)?;
+    if since.is_date() || since.is_number() {
+        let milliseconds = since.number_value(scope)?;
+        filter.since = Some((milliseconds / 1000.0).ceil().max(0.0) as u64);
+    } else if !since.is_undefined() {
+        throw_type_error(scope, "$history.search expects `since` to be a date");
+        return None;
+    }
+
+    let key = v8::String::new(scope, "failed").unwrap().into();
+    let failed = options.get(scope, key)?;
+    if failed.is_boolean() {
+        filter.failed = Some(failed.is_true());
+    } else if !failed.is_undefined() {
+        throw_type_error(scope, "$history.search expects `failed` to be a boolean");
+        return None;
+    }
+
+    Some(filter)
+}
+
+/// Reads the path and the format (`"bash"`, `"zsh"` or `"fish"`) `$history.import` and
+/// `$history.export` take, throwing if they're missing or unknown.
+fn history_file_arguments(
     scope: &mut HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    function: &str,
+) -> Option<(String, shell_history::Format)> {
+    let (path, format) = (args.get(0), args.get(1));
+    if !path.is_string() || !format.is_string() {
+        throw_type_error(
+            scope,
+            &format!("{function} expects a path and a format (\"bash\", \"zsh\" or \"fish\")"),
+        );
+        return None;
+    }
+
+    let path = path.to_rust_string_lossy(scope);
+    match format.to_rust_string_lossy(scope).parse() {
+        Ok(format) => Some((path, format)),
+        Err(error) => {
+            throw_error(scope, &format!("{function}: {error}"));
+            None
+        }
+    }
+}
+
+/// Reads the id of a history entry, ids below 0 counting back from the running command.
+/// Throws if it isn't a number or there's no such entry.
+fn history_id(scope: &mut HandleScope, id: Local<v8::Value>, function: &str) -> Option<u64> {
+    if !id.is_number() {
+        throw_type_error(scope, &format!("{function} expects a history id"));
+        return None;
+    }
+    let id = id.integer_value(scope)?;
+    let history = HISTORY.lock();
+    let entry = match id < 0 {
+        true => history::before_running(&history, id.unsigned_abs() as usize),
+        false => history.iter().find(|entry| entry.id == id as u64),
+    };
+    let id = entry.map(|entry| entry.id);
+    drop(history);
+
+    id.or_else(|| {
+        throw_error(scope, &format!("{function}: no such history entry"));
+        None
+    })
+}
+
+pub fn set_property(
+    scope: &mut HandleScope,
+    object: Local<Object>,
+    key: &str,
+    value: Local<v8::Value>,
+) {
+    let key = v8::String::new(scope, key).unwrap();
+    object.set(scope, key.into(), value);
+}
+
+fn throw_error(scope: &mut HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    scope.throw_exception(exception);
+}
+
+fn throw_type_error(scope: &mut HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::type_error(scope, message);
+    scope.throw_exception(exception);
+}
+
+/// Like [`create_js_function`] for functions without side effects, which lets the eager
+/// preview of the input call them.
+pub fn create_pure_js_function<'s>(
+    scope: &mut HandleScope<'s>,
     global: Local<Object>,
     name: &str,
     callback: impl MapFnTo<FunctionCallback>,
-) {
+) -> Local<'s, Function> {
+    let function = FunctionTemplate::builder(callback)
+        .side_effect_type(SideEffectType::HasNoSideEffect)
+        .build(scope)
+        .get_function(scope)
+        .unwrap();
+    let name = v8::String::new(scope, name).unwrap();
+    global.set(scope, name.into(), function.into());
+    function
+}
+
+pub fn create_js_function<'s>(
+    scope: &mut HandleScope<'s>,
+    global: Local<Object>,
+    name: &str,
+    callback: impl MapFnTo<FunctionCallback>,
+) -> Local<'s, Function> {
     let function = FunctionTemplate::new(scope, callback)
         .get_function(scope)
         .unwrap();
     let name = v8::String::new(scope, name).unwrap();
     global.set(scope, name.into(), function.into());
+    function
 }
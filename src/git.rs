@@ -0,0 +1,180 @@
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Instant,
+};
+
+/// Finds the git directory of the repository `path` is in, following `.git` files
+/// of worktrees and submodules.
+pub fn git_dir(path: &Path) -> Option<PathBuf> {
+    let dot_git = path
+        .ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|dot_git| dot_git.exists())?;
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+
+    let contents = fs::read_to_string(&dot_git).ok()?;
+    let dir = contents.strip_prefix("gitdir:")?.trim();
+    Some(dot_git.parent()?.join(dir))
+}
+
+/// The branch checked out in the repository `path` is in, or the abbreviated commit
+/// when the HEAD is detached. Only reads files, so it's cheap enough for every prompt.
+pub fn branch(path: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir(path)?.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(reference.trim_start_matches("refs/heads/").to_string()),
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+lazy_static! {
+    /// Statuses of the repositories `$git.status()` was asked about, by git directory.
+    static ref STATUSES: Mutex<HashMap<PathBuf, Cached>> = Mutex::new(HashMap::new());
+    /// When the last command finished, statuses checked before then may be outdated.
+    static ref INVALIDATED: Mutex<Instant> = Mutex::new(Instant::now());
+}
+
+/// Set when a status finished refreshing and turned out different, so the prompt
+/// showing it can be repainted.
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    pub branch: Option<String>,
+    pub ahead: usize,
+    pub behind: usize,
+    pub staged: usize,
+    pub modified: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+    /// Whether this is only the branch, read while `git status` is still running.
+    pub pending: bool,
+}
+
+impl Status {
+    pub fn is_dirty(&self) -> bool {
+        self.staged + self.modified + self.untracked + self.conflicted > 0
+    }
+
+    /// Parses the output of `git status --porcelain=v2 --branch`.
+    fn parse(output: &str) -> Self {
+        let mut status = Status::default();
+        for line in output.lines() {
+            let mut fields = line.split(' ');
+            match fields.next() {
+                Some("#") => match (fields.next(), fields.next()) {
+                    (Some("branch.head"), Some("(detached)")) => (),
+                    (Some("branch.head"), Some(head)) => status.branch = Some(head.to_string()),
+                    (Some("branch.oid"), Some(oid)) if status.branch.is_none() => {
+                        status.branch = Some(oid.chars().take(7).collect())
+                    }
+                    (Some("branch.ab"), Some(ahead)) => {
+                        status.ahead = ahead.trim_start_matches('+').parse().unwrap_or(0);
+                        status.behind = fields
+                            .next()
+                            .and_then(|behind| behind.trim_start_matches('-').parse().ok())
+                            .unwrap_or(0);
+                    }
+                    _ => (),
+                },
+                Some("1") | Some("2") => {
+                    let xy = fields.next().unwrap_or("..").as_bytes();
+                    status.staged += (xy.first() != Some(&b'.')) as usize;
+                    status.modified += (xy.get(1) != Some(&b'.')) as usize;
+                }
+                Some("u") => status.conflicted += 1,
+                Some("?") => status.untracked += 1,
+                _ => (),
+            }
+        }
+        status
+    }
+}
+
+struct Cached {
+    status: Option<Status>,
+    /// When the last refresh started.
+    checked: Instant,
+    refreshing: bool,
+}
+
+/// The status of the repository `path` is in, `None` outside of repositories.
+///
+/// Returns what's cached right away and refreshes it in the background when a command
+/// ran since, setting the repaint flag once the refreshed status differs.
+pub fn status(path: &Path) -> Option<Status> {
+    let git_dir = git_dir(path)?;
+    let invalidated = *INVALIDATED.lock();
+
+    let mut statuses = STATUSES.lock();
+    let cached = statuses.entry(git_dir.clone()).or_insert_with(|| Cached {
+        status: None,
+        checked: invalidated,
+        refreshing: false,
+    });
+
+    let outdated = cached.status.is_none() || cached.checked < invalidated;
+    if outdated && !cached.refreshing {
+        cached.refreshing = true;
+        cached.checked = Instant::now();
+        refresh(git_dir, path.to_path_buf());
+    }
+
+    Some(cached.status.clone().unwrap_or_else(|| Status {
+        branch: branch(path),
+        pending: true,
+        ..Status::default()
+    }))
+}
+
+/// Marks every cached status as possibly outdated, called after each command.
+pub fn invalidate() {
+    *INVALIDATED.lock() = Instant::now();
+}
+
+/// Whether a status changed since the last call.
+pub fn take_changed() -> bool {
+    CHANGED.swap(false, Ordering::Relaxed)
+}
+
+fn refresh(git_dir: PathBuf, path: PathBuf) {
+    thread::spawn(move || {
+        let output = Command::new("git")
+            .args(["status", "--porcelain=v2", "--branch"])
+            .current_dir(&path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        let status = match output {
+            Ok(output) if output.status.success() => {
+                Some(Status::parse(&String::from_utf8_lossy(&output.stdout)))
+            }
+            _ => None,
+        };
+
+        let mut statuses = STATUSES.lock();
+        let Some(cached) = statuses.get_mut(&git_dir) else {
+            return;
+        };
+        cached.refreshing = false;
+        // Without git there's still the branch, which stops it from being retried.
+        let status = status.unwrap_or_else(|| Status {
+            branch: branch(&path),
+            ..Status::default()
+        });
+        if cached.status.as_ref() != Some(&status) {
+            cached.status = Some(status);
+            CHANGED.store(true, Ordering::Relaxed);
+        }
+    });
+}
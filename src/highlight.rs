@@ -1,63 +1,382 @@
-use std::fmt::Write;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    ops::Range,
+};
 use syntect::{
-    easy::HighlightLines,
-    highlighting::{Color, Style, Theme},
-    parsing::SyntaxSet,
+    highlighting::{self, HighlightState, RangedHighlightIterator, Style},
+    parsing::{ParseState, ScopeStack, SyntaxSet},
     util::LinesWithEndings,
 };
 
+use crate::{
+    brackets,
+    color::ColorSupport,
+    semantic::{self, Kind, LineAnalysis},
+    theme::{self, THEME},
+};
+
+/// Underlines the part of a history entry that matched the typed text.
+pub const MATCH: &str = "\x1b[4m";
+
+/// Dims the inline suggestion drawn after the cursor.
+pub const SUGGESTION: &str = "\x1b[2m";
+
+/// Inverts the bracket under the cursor and its partner.
+pub const MATCHING_BRACKET: &str = "\x1b[7m";
+
+/// Colours brackets that have no partner.
+pub const ERROR: &str = "\x1b[1;91m";
+
+/// Underlines the location of a syntax error.
+pub const SYNTAX_ERROR: &str = "\x1b[4;91m";
+
+/// Colours the message of a syntax error shown below the input.
+pub const ERROR_MESSAGE: &str = "\x1b[31m";
+
+/// Dims the preview of the input's value shown below it.
+pub const PREVIEW: &str = "\x1b[2m";
+
+/// Colours identifiers naming executables in the PATH.
+pub const EXECUTABLE: &str = "\x1b[1;32m";
+
+/// Colours jssh's builtin functions.
+pub const BUILTIN: &str = "\x1b[1;36m";
+
+/// Colours identifiers of existing variables.
+pub const GLOBAL: &str = "\x1b[34m";
+
+/// Colours calls to functions that don't exist.
+pub const UNKNOWN: &str = "\x1b[91m";
+
+/// Underlines string literals naming existing files.
+pub const PATH: &str = "\x1b[4m";
+
+/// An extra style drawn on top of the syntax colours for a byte range of the source.
+#[derive(Clone)]
+pub struct Mark {
+    pub range: Range<usize>,
+    pub escape: &'static str,
+}
+
+/// A highlighted line of the last source and the states of the parser and the semantic
+/// pass after it, so lines that didn't change since the last keystroke don't have to be
+/// looked at again.
+struct Line {
+    text: String,
+    /// The style of each byte range of `text`.
+    styles: Vec<(Style, Range<usize>)>,
+    parse_state: ParseState,
+    highlight_state: HighlightState,
+    analysis: LineAnalysis,
+    semantic_state: semantic::State,
+    /// Byte ranges of the string literals naming existing files.
+    paths: Vec<Range<usize>>,
+}
+
+/// The states a line is highlighted from.
+#[derive(Clone, PartialEq)]
+struct States {
+    parse: ParseState,
+    highlight: HighlightState,
+    semantic: semantic::State,
+}
+
+impl States {
+    fn after(line: &Line) -> Self {
+        Self {
+            parse: line.parse_state.clone(),
+            highlight: line.highlight_state.clone(),
+            semantic: line.semantic_state.clone(),
+        }
+    }
+}
+
 pub struct Highlighter {
     syntax_set: SyntaxSet,
-    theme: Theme,
+    lines: Vec<Line>,
+    /// The theme generation `lines` were highlighted with.
+    theme_generation: usize,
+    colors: ColorSupport,
+    /// Whether names that aren't builtins or executables are defined, as far as they were
+    /// looked up since the last `forget_lookups`.
+    globals: HashMap<String, bool>,
 }
 
 impl Highlighter {
     pub fn new() -> Self {
         let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme: Theme = serde_json::from_str(include_str!("../theme.json")).unwrap();
 
-        Self { syntax_set, theme }
+        Self {
+            syntax_set,
+            lines: Vec::new(),
+            theme_generation: theme::generation(),
+            colors: ColorSupport::detect(),
+            globals: HashMap::new(),
+        }
     }
 
-    pub fn highlight(&mut self, source: &str) -> String {
-        let syntax = self.syntax_set.find_syntax_by_extension("js").unwrap();
-        let mut highlight_lines = HighlightLines::new(syntax, &self.theme);
+    pub fn highlight(&mut self, source: &str, marks: &[Mark]) -> String {
+        self.update_lines(source);
+
+        let unbalanced = brackets::balance(source)
+            .unbalanced
+            .into_iter()
+            .map(|index| Mark {
+                range: index..index + 1,
+                escape: ERROR,
+            });
+        let marks = marks
+            .iter()
+            .cloned()
+            .chain(unbalanced)
+            .collect::<Vec<Mark>>();
+        // Marks are walked in the order they start, while the escapes of overlapping ones
+        // are still written in the order they were given.
+        let mut order = (0..marks.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|&index| marks[index].range.start);
+        let mut order = order.into_iter().peekable();
+        let mut active: Vec<usize> = Vec::new();
+
+        let declared = self
+            .lines
+            .iter()
+            .flat_map(|line| line.analysis.declared.iter().map(String::as_str))
+            .collect::<HashSet<&str>>();
 
         let mut highlighted_string = String::new();
+        let mut offset = 0;
+        for line in &self.lines {
+            let end = offset + line.text.len();
+            while let Some(index) = order.next_if(|&index| marks[index].range.start < end) {
+                active.push(index);
+            }
+            active.retain(|&index| marks[index].range.end > offset);
+            active.sort();
 
-        for line in LinesWithEndings::from(source) {
-            let ranges: Vec<(Style, &str)> = highlight_lines
-                .highlight_line(line, &self.syntax_set)
-                .unwrap();
-            let escaped = Highlighter::color(&ranges[..]);
+            let line_marks = self
+                .semantic_marks(line, offset, &declared)
+                .into_iter()
+                .chain(active.iter().map(|&index| marks[index].clone()))
+                .collect::<Vec<Mark>>();
+            let ranges = line
+                .styles
+                .iter()
+                .map(|(style, range)| (*style, &line.text[range.clone()]))
+                .collect::<Vec<(Style, &str)>>();
+            let escaped = self.color(&ranges[..], offset, &line_marks);
             write!(highlighted_string, "{escaped}").unwrap();
+            offset = end;
         }
 
         highlighted_string
     }
 
-    fn color_to_hex(Color { r, g, b, .. }: Color) -> u32 {
-        ((r as u32) << 16) + ((g as u32) << 8) + (b as u32)
+    /// Highlights the lines of `source` that changed since the last call. Lines before the
+    /// first changed one are kept, and so are the lines after the last changed one as long
+    /// as the changed lines leave the parser and the semantic pass in the same state as
+    /// before.
+    fn update_lines(&mut self, source: &str) {
+        let theme = THEME.lock();
+        if self.theme_generation != theme::generation() {
+            self.theme_generation = theme::generation();
+            self.lines.clear();
+        }
+
+        let texts = LinesWithEndings::from(source).collect::<Vec<&str>>();
+        let prefix = self
+            .lines
+            .iter()
+            .zip(&texts)
+            .take_while(|(line, text)| line.text == **text)
+            .count();
+        let suffix = self.lines[prefix..]
+            .iter()
+            .rev()
+            .zip(texts[prefix..].iter().rev())
+            .take_while(|(line, text)| line.text == **text)
+            .count();
+
+        let mut old = self.lines.split_off(prefix);
+        let kept = old.split_off(old.len() - suffix);
+
+        let highlighter = highlighting::Highlighter::new(&theme);
+        let mut states = match self.lines.last() {
+            Some(line) => States::after(line),
+            None => {
+                let syntax = self.syntax_set.find_syntax_by_extension("js").unwrap();
+                States {
+                    parse: ParseState::new(syntax),
+                    highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+                    semantic: semantic::State::default(),
+                }
+            }
+        };
+
+        // The states before the first kept line, back when it was last highlighted.
+        let mut kept_states = match old.last() {
+            Some(line) => States::after(line),
+            None => states.clone(),
+        };
+        let changed = texts.len() - suffix;
+        for text in &texts[prefix..changed] {
+            let line = self.highlight_line(text, &highlighter, &mut states);
+            self.lines.push(line);
+        }
+
+        let mut kept = kept.into_iter();
+        for text in &texts[changed..] {
+            if kept_states == states {
+                self.lines.extend(kept);
+                return;
+            }
+            let Some(line) = kept.next() else {
+                break;
+            };
+            kept_states = States::after(&line);
+
+            let line = self.highlight_line(text, &highlighter, &mut states);
+            self.lines.push(line);
+        }
+    }
+
+    /// Highlights and analyses `text` from the given states and advances them past it.
+    fn highlight_line(
+        &self,
+        text: &str,
+        highlighter: &highlighting::Highlighter,
+        states: &mut States,
+    ) -> Line {
+        let operations = states.parse.parse_line(text, &self.syntax_set).unwrap();
+        let styles =
+            RangedHighlightIterator::new(&mut states.highlight, &operations, text, highlighter)
+                .map(|(style, _, range)| (style, range))
+                .collect();
+        let analysis = semantic::analyze_line(text, &mut states.semantic);
+        let paths = analysis
+            .strings
+            .iter()
+            .filter(|(_, content)| semantic::is_existing_path(content))
+            .map(|(range, _)| range.clone())
+            .collect();
+        Line {
+            text: text.to_string(),
+            styles,
+            parse_state: states.parse.clone(),
+            highlight_state: states.highlight.clone(),
+            analysis,
+            semantic_state: states.semantic.clone(),
+            paths,
+        }
+    }
+
+    /// Marks the identifiers of `line` by what they refer to and the string literals
+    /// naming existing files. `declared` holds the names declared anywhere in the input.
+    fn semantic_marks(&self, line: &Line, offset: usize, declared: &HashSet<&str>) -> Vec<Mark> {
+        let mut marks = Vec::new();
+
+        for reference in &line.analysis.references {
+            let name = &line.text[reference.range.clone()];
+            let kind = semantic::resolve(name).or_else(|| match self.globals.get(name)? {
+                true => Some(Kind::Global),
+                false if reference.called && !declared.contains(name) => Some(Kind::Unknown),
+                false => None,
+            });
+            let escape = match kind {
+                Some(Kind::Executable) => EXECUTABLE,
+                Some(Kind::Builtin) => BUILTIN,
+                Some(Kind::Global) => GLOBAL,
+                Some(Kind::Unknown) => UNKNOWN,
+                None => continue,
+            };
+            marks.push(Mark {
+                range: offset + reference.range.start..offset + reference.range.end,
+                escape,
+            });
+        }
+
+        for range in &line.paths {
+            marks.push(Mark {
+                range: offset + range.start..offset + range.end,
+                escape: PATH,
+            });
+        }
+        marks
+    }
+
+    /// Names in `source` that have to be looked up with `resolve_globals` before they
+    /// can be coloured.
+    pub fn unresolved_globals(&mut self, source: &str) -> Vec<String> {
+        self.update_lines(source);
+        let mut names = self
+            .lines
+            .iter()
+            .flat_map(|line| {
+                line.analysis
+                    .references
+                    .iter()
+                    .map(|reference| &line.text[reference.range.clone()])
+            })
+            .filter(|name| !self.globals.contains_key(*name) && semantic::resolve(name).is_none())
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Remembers which names are defined.
+    pub fn resolve_globals(&mut self, resolved: impl IntoIterator<Item = (String, bool)>) {
+        self.globals.extend(resolved);
     }
 
-    fn color(v: &[(Style, &str)]) -> String {
+    /// Forgets which names are defined and which strings name files, as running the input
+    /// may have changed both.
+    pub fn forget_lookups(&mut self) {
+        self.globals.clear();
+        self.lines.clear();
+    }
+
+    fn color(&self, v: &[(Style, &str)], mut offset: usize, marks: &[Mark]) -> String {
         let mut string: String = String::new();
 
         for &(ref style, text) in v.iter() {
-            let fg = style.foreground;
-
-            match Highlighter::color_to_hex(fg) {
-                0x000000 => write!(string, "\x1b[30m{}\x1b[0m", text).unwrap(),
-                0xff0000 => write!(string, "\x1b[31m{}\x1b[0m", text).unwrap(),
-                0x00ff00 => write!(string, "\x1b[32m{}\x1b[0m", text).unwrap(),
-                0xffff00 => write!(string, "\x1b[33m{}\x1b[0m", text).unwrap(),
-                0x0000ff => write!(string, "\x1b[34m{}\x1b[0m", text).unwrap(),
-                0xff00ff => write!(string, "\x1b[35m{}\x1b[0m", text).unwrap(),
-                0x00ffff => write!(string, "\x1b[36m{}\x1b[0m", text).unwrap(),
-                0xffffff => write!(string, "\x1b[37m{}\x1b[0m", text).unwrap(),
-                _ => unreachable!(),
+            let code = self.colors.style(style);
+
+            for (range, escapes) in Highlighter::split(offset..offset + text.len(), marks) {
+                let text = &text[range.start - offset..range.end - offset];
+                write!(string, "\x1b[{code}m{escapes}{text}\x1b[0m").unwrap();
             }
+            offset += text.len();
         }
         string
     }
+
+    /// Splits `range` at every mark boundary inside it and pairs each piece with the
+    /// escapes of the marks covering it.
+    fn split(range: Range<usize>, marks: &[Mark]) -> Vec<(Range<usize>, String)> {
+        let mut boundaries = vec![range.start, range.end];
+        for mark in marks {
+            for boundary in [mark.range.start, mark.range.end] {
+                if range.contains(&boundary) {
+                    boundaries.push(boundary);
+                }
+            }
+        }
+        boundaries.sort();
+        boundaries.dedup();
+
+        boundaries
+            .windows(2)
+            .map(|window| {
+                let piece = window[0]..window[1];
+                let escapes = marks
+                    .iter()
+                    .filter(|mark| mark.range.start <= piece.start && piece.end <= mark.range.end)
+                    .map(|mark| mark.escape)
+                    .collect::<String>();
+                (piece, escapes)
+            })
+            .collect()
+    }
 }
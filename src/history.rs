@@ -0,0 +1,582 @@
+use fs2::FileExt;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{config::CONFIG, path::expand_path, secrets};
+
+lazy_static! {
+    pub static ref HISTORY: Mutex<Vec<Entry>> = {
+        let mut history = load().unwrap_or_default();
+        compact(&mut history);
+        Mutex::new(history)
+    };
+    pub static ref HISTORY_POINTER: Mutex<usize> = Mutex::new(0);
+    /// Tells entries of this shell apart from those of others sharing the history file.
+    pub static ref SESSION: String = format!("{:x}-{}", unix_time().as_nanos(), std::process::id());
+    /// How far into the history file this shell has read, entries past it were added by
+    /// other sessions.
+    static ref READ_OFFSET: Mutex<u64> = Mutex::new(0);
+}
+
+/// The id the next entry gets.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The history file, locked for as long as it's held so sessions don't write over each other.
+///
+/// Loading `HISTORY` takes the lock itself, so `HISTORY` has to be locked before the file
+/// is opened. Otherwise its first use would wait for the lock this session already holds.
+struct HistoryFile(File);
+
+impl HistoryFile {
+    fn open() -> io::Result<Self> {
+        let path = expand_path(&CONFIG.lock().history_file.clone());
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.lock_exclusive()?;
+        Ok(Self(file))
+    }
+
+    /// Reads everything from `offset` to the last complete line and moves the read
+    /// offset past it.
+    fn read_from(&mut self, offset: u64) -> io::Result<String> {
+        let length = self.0.metadata()?.len();
+        // The file was truncated by someone else, there's nothing new to pick up.
+        if length < offset {
+            *READ_OFFSET.lock() = length;
+            return Ok(String::new());
+        }
+
+        self.0.seek(SeekFrom::Start(offset))?;
+        let mut contents = String::new();
+        self.0.read_to_string(&mut contents)?;
+        // A line without its newline is still being written by a session that crashed.
+        contents.truncate(contents.rfind('\n').map_or(0, |end| end + 1));
+        *READ_OFFSET.lock() = offset + contents.len() as u64;
+        Ok(contents)
+    }
+
+    fn append(&mut self, entries: &[Entry]) -> anyhow::Result<()> {
+        let mut contents = String::new();
+        for entry in entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        let end = self.0.seek(SeekFrom::End(0))?;
+        self.0.write_all(contents.as_bytes())?;
+        *READ_OFFSET.lock() = end + contents.len() as u64;
+        Ok(())
+    }
+
+    /// Replaces the contents of the file with `entries`.
+    fn rewrite(&mut self, entries: &[Entry]) -> anyhow::Result<()> {
+        self.0.set_len(0)?;
+        self.append(entries)
+    }
+}
+
+impl Drop for HistoryFile {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+fn load() -> anyhow::Result<Vec<Entry>> {
+    let mut file = HistoryFile::open()?;
+    let contents = file.read_from(0)?;
+    if !is_legacy(&contents) {
+        return Ok(parse(&contents));
+    }
+
+    // History files from before entries were stored as JSON only have the commands, one
+    // per line. They're converted right away, keeping a copy of the original.
+    let path = expand_path(&CONFIG.lock().history_file.clone());
+    let mut backup = path.clone().into_os_string();
+    backup.push(".bak");
+    std::fs::copy(&path, backup)?;
+
+    let entries = contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(Entry::new)
+        .collect::<Vec<Entry>>();
+    file.rewrite(&entries)?;
+    Ok(entries)
+}
+
+/// Whether `contents` is a history file from before entries were stored as JSON.
+fn is_legacy(contents: &str) -> bool {
+    contents
+        .lines()
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| serde_json::from_str::<Entry>(line).is_err())
+}
+
+/// Parses the entries of a history file, one JSON object per line. Lines that aren't
+/// entries are skipped.
+fn parse(contents: &str) -> Vec<Entry> {
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Adds entries other sessions wrote to the history file since it was last read.
+fn import(file: &mut HistoryFile, history: &mut Vec<Entry>) -> anyhow::Result<()> {
+    let offset = *READ_OFFSET.lock();
+    let entries = parse(&file.read_from(offset)?);
+    history.extend(
+        entries
+            .into_iter()
+            .filter(|entry| entry.session.as_deref() != Some(SESSION.as_str())),
+    );
+    compact(history);
+    Ok(())
+}
+
+/// Picks up the entries of other sessions when `share_history` is on.
+pub fn sync() -> anyhow::Result<()> {
+    if !CONFIG.lock().share_history {
+        return Ok(());
+    }
+    let mut history = HISTORY.lock();
+    import(&mut HistoryFile::open()?, &mut history)
+}
+
+/// Appends a finished entry to the history file, so it survives crashes and is seen by
+/// other sessions.
+pub fn append(entry: &Entry) -> anyhow::Result<()> {
+    let share_history = CONFIG.lock().share_history;
+    let mut history = HISTORY.lock();
+    let mut file = HistoryFile::open()?;
+    if share_history {
+        import(&mut file, &mut history)?;
+    }
+    file.append(&[entry.persisted()])
+}
+
+/// Adds the finished entries of this session that aren't in the history file yet, like
+/// ones that failed to be appended, leaving those of other sessions alone. The file is
+/// rewritten when it has grown past `history_size` or has duplicates to erase.
+pub fn save() -> anyhow::Result<()> {
+    let history = HISTORY.lock();
+    let mut file = HistoryFile::open()?;
+    let mut saved = parse(&file.read_from(0)?);
+    let missing = history
+        .iter()
+        .filter(|entry| {
+            entry.session.as_deref() == Some(SESSION.as_str()) && entry.status.is_some()
+        })
+        .map(Entry::persisted)
+        .filter(|entry| !saved.contains(entry))
+        .collect::<Vec<Entry>>();
+    saved.extend(missing.iter().cloned());
+
+    let length = saved.len();
+    compact(&mut saved);
+    match saved.len() == length {
+        true => file.append(&missing),
+        false => file.rewrite(&saved),
+    }
+}
+
+/// Which entries `search` returns besides those containing the query.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Only entries run in this directory.
+    pub cwd: Option<PathBuf>,
+    /// Only entries started at or after this many seconds since the unix epoch.
+    pub since: Option<u64>,
+    /// Only entries that failed (or didn't).
+    pub failed: Option<bool>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &Entry) -> bool {
+        if self.cwd.is_some() && entry.cwd != self.cwd {
+            return false;
+        }
+        if let Some(since) = self.since {
+            match entry.start {
+                Some(start) if start >= since => (),
+                _ => return false,
+            }
+        }
+        if let Some(failed) = self.failed {
+            if entry.failed() != failed {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Returns the indices of the entries containing `query` that pass `filter`, oldest first.
+pub fn search(history: &[Entry], query: &str, filter: &Filter) -> Vec<usize> {
+    history
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.command.contains(query) && filter.matches(entry))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Returns the entry `back` steps before the command this session is running, 1 being
+/// the one right before it. The running command isn't counted, whether or not it was
+/// recorded.
+pub fn before_running(history: &[Entry], back: usize) -> Option<&Entry> {
+    history
+        .iter()
+        .rev()
+        .filter(|entry| !entry.is_running())
+        .nth(back.checked_sub(1)?)
+}
+
+/// Removes the entry with `id` from the history and the history file.
+pub fn delete(id: u64) -> anyhow::Result<Option<Entry>> {
+    let mut history = HISTORY.lock();
+    let mut file = HistoryFile::open()?;
+    let Some(index) = history.iter().position(|entry| entry.id == id) else {
+        return Ok(None);
+    };
+    let entry = history.remove(index);
+
+    let mut saved = parse(&file.read_from(0)?);
+    let persisted = entry.persisted();
+    if let Some(position) = saved.iter().rposition(|other| *other == persisted) {
+        saved.remove(position);
+        file.rewrite(&saved)?;
+    }
+    Ok(Some(entry))
+}
+
+/// Removes every entry from the history and the history file, including those of other
+/// sessions.
+pub fn clear() -> anyhow::Result<()> {
+    let mut history = HISTORY.lock();
+    let mut file = HistoryFile::open()?;
+    history.clear();
+    *HISTORY_POINTER.lock() = 0;
+    file.rewrite(&[])
+}
+
+/// Adds `entries` from elsewhere to the history and the history file, sorted in by when
+/// they started. Returns how many weren't in the history yet.
+pub fn merge(entries: Vec<Entry>) -> anyhow::Result<usize> {
+    let mut history = HISTORY.lock();
+    let mut file = HistoryFile::open()?;
+    let mut saved = parse(&file.read_from(0)?);
+
+    let new = entries
+        .into_iter()
+        .filter(|entry| !history.contains(entry))
+        .collect::<Vec<Entry>>();
+    // Entries without a start are older than any with one.
+    let by_start = |entries: &mut Vec<Entry>| entries.sort_by_key(|entry| entry.start.unwrap_or(0));
+
+    saved.extend(new.iter().map(Entry::persisted));
+    by_start(&mut saved);
+    compact(&mut saved);
+    file.rewrite(&saved)?;
+
+    history.extend(new.iter().cloned());
+    by_start(&mut history);
+    compact(&mut history);
+    Ok(new.len())
+}
+
+/// Adds the entry of a command that's about to run, unless it's ignored.
+pub fn push(entry: Entry) {
+    if is_ignored(&entry.command) {
+        return;
+    }
+    let mut history = HISTORY.lock();
+    history.push(entry);
+    compact(&mut history);
+}
+
+/// Whether `command` is left out of the history because it starts with a space (with
+/// `history_ignore_space`) or matches one of the `history_ignore` patterns.
+fn is_ignored(command: &str) -> bool {
+    let config = CONFIG.lock();
+    if config.history_ignore_space && command.starts_with(' ') {
+        return true;
+    }
+    config
+        .history_ignore
+        .iter()
+        .filter_map(|pattern| Regex::new(pattern).ok())
+        .any(|pattern| pattern.is_match(command))
+}
+
+/// Erases older duplicates (with `history_erase_duplicates`) and the oldest entries past
+/// `history_size`, 0 meaning no limit.
+fn compact(entries: &mut Vec<Entry>) {
+    let (erase_duplicates, size) = {
+        let config = CONFIG.lock();
+        (config.history_erase_duplicates, config.history_size)
+    };
+
+    if erase_duplicates {
+        let mut seen = HashSet::new();
+        let mut keep = entries
+            .iter()
+            .rev()
+            .map(|entry| seen.insert(entry.command.clone()))
+            .collect::<Vec<bool>>();
+        keep.reverse();
+        let mut keep = keep.into_iter();
+        entries.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    if size > 0 && entries.len() > size {
+        entries.drain(..entries.len() - size);
+    }
+}
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Time since the unix epoch.
+pub fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Tells entries apart for as long as the shell runs, unlike their position in the
+    /// history which changes as entries are added and removed. It isn't saved.
+    #[serde(skip, default = "next_id")]
+    pub id: u64,
+    pub command: String,
+    /// Seconds since the unix epoch when the command was started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<u64>,
+    /// How long the command ran in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    /// Directory the command was run in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Exit status like `$lastStatus`, unknown while the command is still running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+}
+
+impl Entry {
+    /// An entry without anything known about it besides the command.
+    pub fn new(command: &str) -> Self {
+        Self {
+            id: next_id(),
+            command: command.to_string(),
+            start: None,
+            duration: None,
+            cwd: None,
+            status: None,
+            session: None,
+        }
+    }
+
+    /// An entry for a command started right now in the current directory.
+    pub fn started(command: &str) -> Self {
+        Self {
+            start: Some(unix_time().as_secs()),
+            cwd: env::current_dir().ok(),
+            session: Some(SESSION.clone()),
+            ..Self::new(command)
+        }
+    }
+
+    /// The entry as it's written to the history file, with secrets redacted when
+    /// `history_redact_secrets` is on.
+    pub fn persisted(&self) -> Self {
+        match CONFIG.lock().history_redact_secrets {
+            true => Self {
+                command: secrets::redact(&self.command),
+                ..self.clone()
+            },
+            false => self.clone(),
+        }
+    }
+
+    /// Whether this is the command this session is running right now, the only entry of
+    /// the session without a status.
+    pub fn is_running(&self) -> bool {
+        self.status.is_none() && self.session.as_deref() == Some(SESSION.as_str())
+    }
+
+    /// Whether the command failed to compile, threw or ran an executable that failed.
+    pub fn failed(&self) -> bool {
+        self.status.is_some_and(|status| status != 0)
+    }
+}
+
+/// Entries are the same when everything but their id is.
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.start == other.start
+            && self.duration == other.duration
+            && self.cwd == other.cwd
+            && self.status == other.status
+            && self.session == other.session
+    }
+}
+
+impl Eq for Entry {}
+
+/// How the typed text is matched against history entries while browsing with Up/Down.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HistorySearch {
+    Prefix,
+    Substring,
+}
+
+impl HistorySearch {
+    /// Returns the byte range of `query` inside `entry` if the entry matches.
+    pub fn find(self, entry: &str, query: &str) -> Option<Range<usize>> {
+        match self {
+            HistorySearch::Prefix => entry.starts_with(query).then_some(0..query.len()),
+            HistorySearch::Substring => entry.find(query).map(|start| start..start + query.len()),
+        }
+    }
+}
+
+pub enum Direction {
+    Older,
+    Newer,
+}
+
+/// Walks from `pointer` (the number of steps back from the newest entry) in `direction`
+/// and returns the pointer of the next entry matching `query` that differs from `current`.
+///
+/// An empty `query` matches every entry, which gives plain history navigation.
+pub fn find_entry(
+    history: &[Entry],
+    pointer: usize,
+    query: &str,
+    current: &str,
+    direction: Direction,
+) -> Option<usize> {
+    let mode = CONFIG.lock().history_search;
+    let matches = |pointer: &usize| {
+        let entry = &history[history.len() - pointer].command;
+        entry != current && (query.is_empty() || mode.find(entry, query).is_some())
+    };
+
+    match direction {
+        Direction::Older => (pointer + 1..=history.len()).find(matches),
+        Direction::Newer => (1..pointer).rev().find(matches),
+    }
+}
+
+/// Returns the rest of the most recent successful entry starting with `input`, preferring
+/// entries that were run in the current directory.
+pub fn suggest(history: &[Entry], input: &str) -> Option<String> {
+    if input.is_empty() {
+        return None;
+    }
+
+    let cwd = env::current_dir().ok();
+    let mut candidates = history.iter().rev().filter(|entry| {
+        !entry.failed() && entry.command.len() > input.len() && entry.command.starts_with(input)
+    });
+
+    let first = candidates.next()?;
+    let entry = if first.cwd.is_some() && first.cwd == cwd {
+        first
+    } else {
+        candidates
+            .find(|entry| entry.cwd.is_some() && entry.cwd == cwd)
+            .unwrap_or(first)
+    };
+
+    Some(entry.command[input.len()..].to_string())
+}
+
+/// Returns the length of the first word of a suggestion, including the separators before it.
+pub fn next_word(suggestion: &str) -> usize {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+    let start = suggestion.find(is_word).unwrap_or(suggestion.len());
+    suggestion[start..]
+        .find(|c: char| !is_word(c))
+        .map_or(suggestion.len(), |end| start + end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(command: &str) -> Entry {
+        Entry {
+            status: Some(0),
+            ..Entry::started(command)
+        }
+    }
+
+    #[test]
+    fn ids_stay_the_same_when_entries_are_removed() {
+        let mut history = vec![finished("a"), finished("b"), finished("c")];
+        let ids = history.iter().map(|entry| entry.id).collect::<Vec<u64>>();
+
+        history.remove(0);
+        let c = history.iter().find(|entry| entry.id == ids[2]).unwrap();
+        assert_eq!(c.command, "c");
+        assert!(history.iter().all(|entry| entry.id != ids[0]));
+    }
+
+    #[test]
+    fn loaded_entries_get_new_ids() {
+        let entry = finished("ls");
+        let loaded = parse(&format!("{}\n", serde_json::to_string(&entry).unwrap()));
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0], entry);
+        assert_ne!(loaded[0].id, entry.id);
+    }
+
+    #[test]
+    fn counts_back_from_before_the_running_command() {
+        let mut history = vec![finished("a"), finished("b"), Entry::started("$edit()")];
+        assert_eq!(before_running(&history, 1).unwrap().command, "b");
+        assert_eq!(before_running(&history, 2).unwrap().command, "a");
+        assert!(before_running(&history, 3).is_none());
+        assert!(before_running(&history, 0).is_none());
+
+        // An ignored command isn't in the history at all.
+        history.pop();
+        assert_eq!(before_running(&history, 1).unwrap().command, "b");
+    }
+
+    #[test]
+    fn entries_of_other_sessions_are_never_running() {
+        let other = Entry {
+            session: Some(String::from("other")),
+            ..Entry::new("sleep 10")
+        };
+        let history = vec![finished("a"), other, Entry::new("imported")];
+        assert_eq!(before_running(&history, 1).unwrap().command, "imported");
+        assert_eq!(before_running(&history, 2).unwrap().command, "sleep 10");
+    }
+}
@@ -2,6 +2,8 @@ use std::io::{self, Write};
 
 use lazy_static::lazy_static;
 
+use crate::terminal::TERMINAL;
+
 macro_rules! lazy_statics {
     ($($name:ident : $value:expr),*) => {
         lazy_static! {
@@ -23,7 +25,8 @@ macro_rules! lazy_statics {
 lazy_statics! {
     NEWLINE: "\n",
     RETURN: "\r",
-    NEWLINE_RETURN: "\n\r"
+    // Raw mode doesn't turn newlines into carriage returns as well.
+    NEWLINE_RETURN: if TERMINAL.interactive { "\n\r" } else { "\n" }
 }
 
 pub fn flush() {
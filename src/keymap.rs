@@ -0,0 +1,412 @@
+use anyhow::bail;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rusty_v8::{self as v8, Global, HandleScope};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::{config::CONFIG, editor::Editor, io::NEWLINE_RETURN};
+
+lazy_static! {
+    pub static ref KEYMAP: Mutex<Keymap> = {
+        let mut keymap = Keymap::default();
+
+        for (keys, action) in CONFIG.lock().keymap.iter() {
+            match parse_sequence(keys) {
+                Ok(sequence) => keymap.bind(sequence, Binding::Action(*action)),
+                Err(error) => eprint!("Invalid keymap entry `{keys}`: {error}{NEWLINE_RETURN}"),
+            }
+        }
+
+        Mutex::new(keymap)
+    };
+}
+
+/// A built-in line editor action that keys can be bound to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    AcceptLine,
+    InsertNewline,
+    Cancel,
+    HistoryPrevious,
+    HistoryNext,
+    BackwardChar,
+    ForwardChar,
+    BackwardWord,
+    ForwardWord,
+    BeginningOfLine,
+    EndOfLine,
+    BackwardDeleteChar,
+    DeleteChar,
+    BackwardKillWord,
+    BackwardKillLine,
+    KillLine,
+    EditExternally,
+    Undo,
+    Redo,
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_value(serde_json::Value::String(
+            name.to_string(),
+        ))?)
+    }
+}
+
+/// A key together with the modifiers held while pressing it, e.g. `ctrl-x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            // Control chords are told apart by Shift for letters only, for anything else
+            // Shift is already part of the character itself.
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => {
+                if c.is_ascii_uppercase() {
+                    modifiers.insert(KeyModifiers::SHIFT);
+                }
+                if !c.is_ascii_alphabetic() {
+                    modifiers.remove(KeyModifiers::SHIFT);
+                }
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            KeyCode::Char(c) => {
+                let c = match modifiers.contains(KeyModifiers::SHIFT) {
+                    true => c.to_ascii_uppercase(),
+                    false => c,
+                };
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c)
+            }
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            KeyCode::BackTab => {
+                modifiers.insert(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    /// The character this chord types if it isn't bound to anything.
+    pub fn character(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    /// Parses chords like `ctrl-g`, `alt-right` or `shift-tab`.
+    fn from_str(chord: &str) -> anyhow::Result<Self> {
+        let (modifier_names, key) = match chord.rsplit_once('-') {
+            Some((modifiers, "")) => (modifiers.trim_end_matches('-'), "-"),
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", chord),
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_names.split('-').filter(|name| !name.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "option" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier `{modifier}` in `{chord}`"),
+            };
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "esc" | "escape" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name if name.len() > 1 && name.starts_with('f') && name[1..].parse::<u8>().is_ok() => {
+                KeyCode::F(name[1..].parse()?)
+            }
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => bail!("unknown key `{key}` in `{chord}`"),
+                }
+            }
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+/// Parses a space separated sequence of chords like `ctrl-x ctrl-e`.
+pub fn parse_sequence(keys: &str) -> anyhow::Result<Vec<KeyChord>> {
+    let sequence = keys
+        .split_whitespace()
+        .map(KeyChord::from_str)
+        .collect::<anyhow::Result<Vec<KeyChord>>>()?;
+    if sequence.is_empty() {
+        bail!("no keys given");
+    }
+    Ok(sequence)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Action(Action),
+    /// Index of a JavaScript function in the isolate's [`KeyCallbacks`].
+    Function(usize),
+}
+
+/// JavaScript functions bound with `$bindKey`, stored in a slot of the isolate.
+#[derive(Default)]
+pub struct KeyCallbacks(pub Vec<Global<v8::Function>>);
+
+pub enum Resolved {
+    Binding(Binding),
+    /// The keys so far are the start of a longer sequence.
+    Pending,
+    /// Nothing is bound to the key, or to the sequence it ended.
+    Unbound(KeyChord),
+}
+
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyChord>, Binding)>,
+    pending: Vec<KeyChord>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        use KeyCode::*;
+
+        let none = KeyModifiers::NONE;
+        let ctrl = KeyModifiers::CONTROL;
+        let alt = KeyModifiers::ALT;
+
+        // TODO: Bind Tab once there is completion.
+        let defaults = [
+            (Enter, none, AcceptLine),
+            (Enter, alt, InsertNewline),
+            (Char('c'), ctrl, Cancel),
+            (Up, none, HistoryPrevious),
+            (Down, none, HistoryNext),
+            (Left, none, BackwardChar),
+            (Right, none, ForwardChar),
+            (Left, alt, BackwardWord),
+            (Right, alt, ForwardWord),
+            (Left, ctrl, BackwardWord),
+            (Right, ctrl, ForwardWord),
+            (Home, none, BeginningOfLine),
+            (Char('a'), ctrl, BeginningOfLine),
+            (End, none, EndOfLine),
+            (Char('e'), ctrl, EndOfLine),
+            (Backspace, none, BackwardDeleteChar),
+            (Delete, none, DeleteChar),
+            (Backspace, alt, BackwardKillWord),
+            (Char('w'), ctrl, BackwardKillWord),
+            (Char('u'), ctrl, BackwardKillLine),
+            (Char('k'), ctrl, KillLine),
+            // Terminals send Ctrl-_ (and often Ctrl-/) as the same byte as Ctrl-7.
+            (Char('_'), ctrl, Undo),
+            (Char('7'), ctrl, Undo),
+            (Char('/'), ctrl, Undo),
+            (Char('z'), ctrl, Undo),
+            (Char('y'), ctrl, Redo),
+            (Char('z'), ctrl | KeyModifiers::SHIFT, Redo),
+        ];
+        // Sequences of several chords, like Emacs' Ctrl-X prefix.
+        let sequences = [([(Char('x'), ctrl), (Char('e'), ctrl)], EditExternally)];
+
+        let singles = defaults
+            .into_iter()
+            .map(|(code, modifiers, action)| (vec![KeyChord::new(code, modifiers)], action));
+        let sequences = sequences.into_iter().map(|(chords, action)| {
+            let chords = chords
+                .into_iter()
+                .map(|(code, modifiers)| KeyChord::new(code, modifiers))
+                .collect();
+            (chords, action)
+        });
+
+        Self {
+            bindings: singles
+                .chain(sequences)
+                .map(|(keys, action)| (keys, Binding::Action(action)))
+                .collect(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Binds `sequence`, replacing whatever was bound to it before.
+    pub fn bind(&mut self, sequence: Vec<KeyChord>, binding: Binding) {
+        self.bindings.retain(|(keys, _)| *keys != sequence);
+        self.bindings.push((sequence, binding));
+    }
+
+    /// Feeds a key press into the keymap, which remembers it if it starts a longer sequence.
+    pub fn resolve(&mut self, chord: KeyChord) -> Resolved {
+        self.pending.push(chord);
+
+        let exact = self
+            .bindings
+            .iter()
+            .find(|(keys, _)| *keys == self.pending)
+            .map(|(_, binding)| *binding);
+        if let Some(binding) = exact {
+            self.pending.clear();
+            return Resolved::Binding(binding);
+        }
+
+        let is_prefix = self
+            .bindings
+            .iter()
+            .any(|(keys, _)| keys.starts_with(&self.pending));
+        if is_prefix {
+            return Resolved::Pending;
+        }
+
+        self.pending.clear();
+        Resolved::Unbound(chord)
+    }
+}
+
+/// Calls the JavaScript function bound to a key with an object holding the buffer and the
+/// cursor (as a string index), then picks up whatever the function changed them to.
+pub fn call_function(scope: &mut HandleScope, index: usize, editor: &mut Editor) {
+    let Some(function) = scope
+        .get_slot::<KeyCallbacks>()
+        .and_then(|callbacks| callbacks.0.get(index).cloned())
+    else {
+        return;
+    };
+    let function = v8::Local::new(scope, function);
+
+    let state = v8::Object::new(scope);
+    let buffer_key = v8::String::new(scope, "buffer").unwrap().into();
+    let cursor_key = v8::String::new(scope, "cursor").unwrap().into();
+
+    let buffer = v8::String::new(scope, &editor.buffer).unwrap().into();
+    let cursor = v8::Number::new(scope, editor.utf16_cursor() as f64).into();
+    state.set(scope, buffer_key, buffer);
+    state.set(scope, cursor_key, cursor);
+
+    let undefined = v8::undefined(scope).into();
+    if function.call(scope, undefined, &[state.into()]).is_none() {
+        return;
+    }
+
+    let buffer = state.get(scope, buffer_key).unwrap();
+    let cursor = state.get(scope, cursor_key).unwrap();
+    editor.replace(
+        &buffer.to_rust_string_lossy(scope),
+        cursor.number_value(scope).unwrap_or(f64::MAX).max(0.0) as usize,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_chords() {
+        let cases = [
+            ("a", chord(KeyCode::Char('a'), KeyModifiers::NONE)),
+            ("ctrl-g", chord(KeyCode::Char('g'), KeyModifiers::CONTROL)),
+            (
+                "Control-G",
+                chord(
+                    KeyCode::Char('g'),
+                    KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+                ),
+            ),
+            ("alt-right", chord(KeyCode::Right, KeyModifiers::ALT)),
+            ("meta-enter", chord(KeyCode::Enter, KeyModifiers::ALT)),
+            ("shift-tab", chord(KeyCode::BackTab, KeyModifiers::SHIFT)),
+            ("shift-a", chord(KeyCode::Char('A'), KeyModifiers::NONE)),
+            (
+                "ctrl-alt-space",
+                chord(
+                    KeyCode::Char(' '),
+                    KeyModifiers::CONTROL | KeyModifiers::ALT,
+                ),
+            ),
+            ("ctrl--", chord(KeyCode::Char('-'), KeyModifiers::CONTROL)),
+            ("-", chord(KeyCode::Char('-'), KeyModifiers::NONE)),
+            ("f5", chord(KeyCode::F(5), KeyModifiers::NONE)),
+        ];
+        for (keys, expected) in cases {
+            assert_eq!(keys.parse::<KeyChord>().unwrap(), expected, "{keys}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_chords() {
+        for keys in ["hyper-a", "ctrl-foo", "pgup"] {
+            assert!(keys.parse::<KeyChord>().is_err(), "{keys}");
+        }
+    }
+
+    #[test]
+    fn parses_sequences() {
+        assert_eq!(
+            parse_sequence("ctrl-x  ctrl-e").unwrap(),
+            [
+                chord(KeyCode::Char('x'), KeyModifiers::CONTROL),
+                chord(KeyCode::Char('e'), KeyModifiers::CONTROL),
+            ]
+        );
+        assert!(parse_sequence("").is_err());
+        assert!(parse_sequence("ctrl-x bogus-e").is_err());
+    }
+
+    #[test]
+    fn resolves_sequences() {
+        let mut keymap = Keymap::default();
+        let ctrl = |c| chord(KeyCode::Char(c), KeyModifiers::CONTROL);
+        assert!(matches!(keymap.resolve(ctrl('x')), Resolved::Pending));
+        assert!(matches!(
+            keymap.resolve(ctrl('e')),
+            Resolved::Binding(Binding::Action(Action::EditExternally))
+        ));
+        assert!(matches!(
+            keymap.resolve(ctrl('e')),
+            Resolved::Binding(Binding::Action(Action::EndOfLine))
+        ));
+    }
+}
@@ -1,5 +1,21 @@
+pub mod brackets;
+pub mod color;
+pub mod editor;
 pub mod functions;
+pub mod git;
 pub mod path;
 pub mod io;
 pub mod config;
-pub mod highlight;
\ No newline at end of file
+pub mod highlight;
+pub mod history;
+pub mod keymap;
+pub mod preview;
+pub mod prompt;
+pub mod render;
+pub mod secrets;
+pub mod semantic;
+pub mod shell_history;
+pub mod signature;
+pub mod syntax;
+pub mod terminal;
+pub mod theme;
\ No newline at end of file
@@ -1,35 +1,36 @@
-use crossterm::{
-    cursor::{MoveLeft, RestorePosition, SavePosition},
-    event::{
-        self, KeyCode, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
-        PushKeyboardEnhancementFlags,
-    },
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
-};
+use crossterm::event::{self, Event};
 use jssh::{
     config::CONFIG,
-    functions::{create_functions, HISTORY, HISTORY_POINTER, RUNNING},
-    highlight::Highlighter,
+    editor::{Editor, PENDING_INPUT},
+    functions::{create_functions, EXIT_STATUS, RUNNING},
+    git,
+    highlight::{Highlighter, Mark, ERROR_MESSAGE, PREVIEW, SYNTAX_ERROR},
+    history::{self, Entry, HISTORY, HISTORY_POINTER},
     io::{flush, NEWLINE_RETURN},
+    keymap::{self, Action, Binding, Resolved, KEYMAP},
     path::expand_path,
+    preview::Previewer,
+    prompt::{self, LastCommand, Prompt},
+    render::{Frame, Renderer},
+    signature::{self, Call, Signature},
+    syntax::{self, SyntaxError},
+    terminal::{self, TERMINAL},
 };
 use rusty_v8::{self as v8, V8};
-use std::{io::stdout, panic};
+use std::{
+    io::{stdin, IsTerminal},
+    panic,
+    time::{Duration, Instant},
+};
 use v8::HandleScope;
 
-static PROMPT: &str = ">";
-
 fn de_init() {
     unsafe { V8::dispose() };
     V8::shutdown_platform();
-    disable_raw_mode().unwrap();
-    execute!(stdout(), PopKeyboardEnhancementFlags).unwrap();
-    std::fs::write(
-        expand_path(&(*CONFIG.lock().history_file.clone())),
-        (*HISTORY.lock()).join("\n"),
-    )
-    .unwrap();
+    terminal::de_init().unwrap();
+    if let Err(error) = history::save() {
+        eprint!("Couldn't save the history: {error}{NEWLINE_RETURN}");
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -43,12 +44,7 @@ fn main() -> anyhow::Result<()> {
         std::fs::write(start_up_file, include_str!("./scripts/start_up_file.js"))?;
     }
 
-    enable_raw_mode()?;
-    execute!(
-        stdout(),
-        PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
-    )
-    .unwrap();
+    terminal::init()?;
 
     {
         let platform = v8::new_default_platform(0, false).make_shared();
@@ -67,6 +63,7 @@ fn main() -> anyhow::Result<()> {
         let global = context.global(scope);
 
         create_functions(scope, global);
+        prompt::record(scope, LastCommand::default());
 
         let source_code = format!(r#"$source("{}")"#, &*CONFIG.lock().start_up_file);
 
@@ -75,9 +72,14 @@ fn main() -> anyhow::Result<()> {
         v8_script.run(scope).unwrap();
 
         let highlighter = &mut Highlighter::new();
+        let renderer = &mut Renderer::new();
+        let previewer = &mut Previewer::new(scope);
 
         while *RUNNING.lock() {
-            let code = loop_callback(scope, highlighter)?;
+            let code = match TERMINAL.interactive {
+                true => loop_callback(scope, highlighter, renderer, previewer)?,
+                false => plain_loop_callback(scope)?,
+            };
             match code {
                 LoopCodes::Ok => (),
                 LoopCodes::Exit => break,
@@ -92,6 +94,7 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[derive(Clone, Copy)]
 pub enum LoopCodes {
     Ok = 0,
     Exit = 1,
@@ -103,153 +106,296 @@ pub enum LoopCodes {
 fn loop_callback(
     scope: &mut HandleScope,
     highlighter: &mut Highlighter,
+    renderer: &mut Renderer,
+    previewer: &mut Previewer,
 ) -> anyhow::Result<LoopCodes> {
-    print!("{PROMPT} {}", SavePosition);
-    flush();
-
-    let mut string = String::new();
-    let mut temporary_string = String::new();
-
-    let mut cursor_index: usize = 0;
+    if let Err(error) = history::sync() {
+        eprint!("Couldn't read the history: {error}{NEWLINE_RETURN}");
+    }
+    let mut prompt = Prompt::evaluate(scope);
+    renderer.print_above(&prompt.above);
+    // The last input may have defined or deleted variables and files.
+    highlighter.forget_lookups();
+
+    let mut editor = Editor::new();
+    if let Some(input) = PENDING_INPUT.lock().take() {
+        editor.insert_str(&input);
+    }
+    let mut live = Live::default();
+    draw(&prompt, &editor, highlighter, renderer, None);
 
     loop {
         if event::poll(std::time::Duration::from_millis(50))? {
-            if let event::Event::Key(key_event) = event::read()? {
-                match key_event.code {
-                    KeyCode::Enter => {
-                        print!("\r");
-                        break;
-                    }
-                    KeyCode::Up => {
-                        let history_length = (*HISTORY.lock()).len();
-                        if *HISTORY_POINTER.lock() == history_length {
-                            continue;
-                        }
-
-                        *HISTORY_POINTER.lock() += 1;
-
-                        if *HISTORY_POINTER.lock() == 1 {
-                            temporary_string = string.clone();
-                        }
-
-                        let history = HISTORY.lock();
-                        let entry = history
-                            .get(history_length - *HISTORY_POINTER.lock())
-                            .unwrap();
-                        string = entry.to_string();
-                        print!("\r{}{PROMPT} {}", Clear(ClearType::FromCursorDown), entry)
-                    }
-                    KeyCode::Down => {
-                        let history_length = (*HISTORY.lock()).len();
-                        if *HISTORY_POINTER.lock() == 0 {
-                            continue;
-                        }
-
-                        *HISTORY_POINTER.lock() -= 1;
-
-                        if *HISTORY_POINTER.lock() == 0 {
-                            string = temporary_string.clone();
-                        } else {
-                            let history = HISTORY.lock();
-                            let entry = history
-                                .get(history_length - *HISTORY_POINTER.lock())
-                                .unwrap();
-                            string = entry.to_string();
-                        };
-                        print!("\r{}{PROMPT} {}", Clear(ClearType::FromCursorDown), string)
-                    }
-                    KeyCode::Left => {
-                        // TODO: Add modifier keys to move around.
-                        if cursor_index == string.len() {
-                            continue;
-                        }
-                        cursor_index += 1;
-                    }
-                    KeyCode::Right => {
-                        // TODO: Add modifier keys to move around.
-                        if cursor_index == 0 {
-                            continue;
-                        }
-                        cursor_index -= 1;
-                    }
-                    KeyCode::Char(c) => {
-                        if c == 'c' && key_event.modifiers.bits() == 0b0000_0010 {
-                            print!("{NEWLINE_RETURN}");
+            match event::read()? {
+                Event::Key(key_event) => {
+                    let resolved = KEYMAP.lock().resolve(key_event.into());
+                    match resolved {
+                        Resolved::Binding(Binding::Action(Action::AcceptLine)) => break,
+                        Resolved::Binding(Binding::Action(Action::Cancel)) => {
+                            draw(&prompt, &editor, highlighter, renderer, None);
+                            renderer.finish();
+                            let cancelled = LastCommand {
+                                status: LoopCodes::Cancelled as i32,
+                                duration: Duration::ZERO,
+                            };
+                            prompt::record(scope, cancelled);
                             return Ok(LoopCodes::Cancelled);
                         }
-
-                        string.insert(string.len() - cursor_index, c);
-                    }
-                    KeyCode::Backspace => {
-                        // TODO: Add modifier keys for deletion.
-                        // let is_alt = KeyModifiers::ALT.contains(key_event.modifiers);
-                        if string.len() == 0 {
-                            *HISTORY_POINTER.lock() = 0;
-                            continue;
+                        Resolved::Binding(Binding::Action(action)) => {
+                            if let Err(error) = editor.perform(action) {
+                                renderer.finish();
+                                eprint!("{error}{NEWLINE_RETURN}");
+                            }
                         }
-                        // string;
-                        // string.clear();
-                        // TODO: Fix panic on 'ä' etc.
-                        string.remove(string.len() - cursor_index - 1);
-                    }
-                    KeyCode::Delete => {
-                        // TODO: Add modifier keys for deletion.
-                        if string.len() == 0 || cursor_index == 0 || cursor_index == string.len() {
-                            *HISTORY_POINTER.lock() = 0;
-                            continue;
+                        Resolved::Binding(Binding::Function(index)) => {
+                            keymap::call_function(scope, index, &mut editor)
                         }
-
-                        // TODO: Fix panic on 'ä' etc.
-                        string.remove(string.len() - cursor_index);
-                        cursor_index -= 1
-                    }
-                    KeyCode::Tab => {
-                        // TODO: Add detection and autocompletion for certain arguments and functions/variables.
+                        Resolved::Pending => continue,
+                        Resolved::Unbound(chord) => match chord.character() {
+                            Some(c) => editor.insert(c),
+                            None => continue,
+                        },
                     }
-                    _ => continue,
                 }
+                Event::Paste(text) => editor.insert_str(&text),
+                Event::Resize(columns, _) => renderer.resize(columns),
+                _ => continue,
             }
-            crossterm::terminal::window_size().unwrap();
-            let highlighted = highlighter.highlight(&string);
-            let left = if cursor_index == 0 {
-                String::new()
-            } else {
-                MoveLeft(cursor_index as u16).to_string()
-            };
-            print!(
-                "{}{}{highlighted}{left}",
-                RestorePosition,
-                Clear(ClearType::FromCursorDown)
-            );
-            flush()
+        } else if git::take_changed() {
+            // A git status the prompt may show came in.
+            prompt = Prompt::evaluate(scope);
+            renderer.collapse();
+            renderer.print_above(&prompt.above);
+        } else if !live.update(scope, previewer, highlighter, &editor) {
+            continue;
         }
+
+        draw(&prompt, &editor, highlighter, renderer, Some(&live));
     }
 
-    print!("{NEWLINE_RETURN}");
+    let transient_prompt = CONFIG.lock().transient_prompt;
+    match transient_prompt {
+        true => {
+            renderer.collapse();
+            let transient = Prompt::transient(scope);
+            draw(&transient, &editor, highlighter, renderer, None);
+        }
+        false => draw(&prompt, &editor, highlighter, renderer, None),
+    }
+    renderer.finish();
 
-    let input = string;
     *HISTORY_POINTER.lock() = 0;
+    Ok(execute(scope, &editor.buffer))
+}
+
+/// Reads a line of input without the line editor, for dumb terminals and input that
+/// doesn't come from a terminal at all, and runs it.
+fn plain_loop_callback(scope: &mut HandleScope) -> anyhow::Result<LoopCodes> {
+    if let Err(error) = history::sync() {
+        eprint!("Couldn't read the history: {error}{NEWLINE_RETURN}");
+    }
+    // Scripts piped into jssh don't need prompts between their lines.
+    let prompted = stdin().is_terminal();
+    if prompted {
+        let prompt = Prompt::evaluate(scope);
+        if !prompt.above.is_empty() {
+            print!("{}{NEWLINE_RETURN}", terminal::styled(&prompt.above));
+        }
+        print!("{}", terminal::styled(&prompt.line));
+        flush();
+    }
 
+    let mut input = String::new();
+    if stdin().read_line(&mut input)? == 0 {
+        if prompted {
+            print!("{NEWLINE_RETURN}");
+        }
+        return Ok(LoopCodes::Exit);
+    }
+    Ok(execute(scope, input.trim_end_matches(['\n', '\r'])))
+}
+
+/// Runs `input`, records it in the history and sets `$lastStatus` and `$lastDuration`.
+fn execute(scope: &mut HandleScope, input: &str) -> LoopCodes {
     if input == "" {
-        return Ok(LoopCodes::Ok);
-    } else {
-        (*HISTORY.lock()).push(input.clone());
+        return LoopCodes::Ok;
     }
+    let entry = Entry::started(input);
+    history::push(entry.clone());
+
+    *EXIT_STATUS.lock() = 0;
+    let started = Instant::now();
+    let code = run(scope, input);
+    let duration = started.elapsed();
+    git::invalidate();
+
+    // Inputs that ran fine take the status of the last executable they ran.
+    let status = match code {
+        LoopCodes::Ok => *EXIT_STATUS.lock(),
+        code => code as i32,
+    };
+    let finished = Entry {
+        status: Some(status),
+        duration: Some(duration.as_millis() as u64),
+        ..entry.clone()
+    };
+    // The entry is gone if it was ignored or the command deleted it.
+    let recorded = match HISTORY
+        .lock()
+        .iter_mut()
+        .rev()
+        .find(|other| other.id == entry.id)
+    {
+        Some(entry) => {
+            *entry = finished.clone();
+            true
+        }
+        None => false,
+    };
+    if recorded {
+        if let Err(error) = history::append(&finished) {
+            eprint!("Couldn't save the command to the history: {error}{NEWLINE_RETURN}");
+        }
+    }
+    prompt::record(scope, LastCommand { status, duration });
 
-    let code = v8::String::new(scope, &input).unwrap();
+    let threshold = CONFIG.lock().slow_command_threshold;
+    if threshold > 0 && duration >= Duration::from_millis(threshold) {
+        let took = prompt::format_duration(duration);
+        let took = format!("{PREVIEW}took {took}\x1b[0m");
+        print!("{}{NEWLINE_RETURN}", terminal::styled(&took));
+    }
+    code
+}
+
+fn run(scope: &mut HandleScope, input: &str) -> LoopCodes {
+    let code = v8::String::new(scope, input).unwrap();
     let script = match v8::Script::compile(scope, code, None) {
         Some(compiled_script) => compiled_script,
-        None => return Ok(LoopCodes::CompilationFailed),
+        None => return LoopCodes::CompilationFailed,
     };
     let result = match script.run(scope) {
         Some(result) => result,
-        None => return Ok(LoopCodes::RuntimeFailed),
+        None => return LoopCodes::RuntimeFailed,
     };
 
     if !result.is_undefined() {
         print!("{}{NEWLINE_RETURN}", result.to_rust_string_lossy(scope));
     }
-    return Ok(LoopCodes::Ok);
+    LoopCodes::Ok
+}
+
+/// What gets worked out about the input while the user pauses typing.
+#[derive(Default)]
+struct Live {
+    /// The input the rest was worked out for.
+    checked: String,
+    syntax_error: Option<SyntaxError>,
+    preview: Option<String>,
+    /// The call the cursor is in and the signature of what it calls.
+    call: Option<Call>,
+    signature: Option<Signature>,
+    /// Whether the signature is still being looked up in the background.
+    looking_up: bool,
+}
+
+impl Live {
+    /// Checks and previews the input if it changed since the last time, looks up the
+    /// variables it uses and the signature of the call the cursor is in. Returns whether
+    /// anything changed.
+    fn update(
+        &mut self,
+        scope: &mut HandleScope,
+        previewer: &mut Previewer,
+        highlighter: &mut Highlighter,
+        editor: &Editor,
+    ) -> bool {
+        let mut changed = false;
+        let input = &editor.buffer;
+        if self.checked != *input {
+            self.checked = input.to_string();
+            self.syntax_error = syntax::check(scope, input);
+            self.preview = match self.syntax_error {
+                Some(_) => None,
+                None => previewer.preview(input),
+            };
+            changed = true;
+
+            let names = highlighter.unresolved_globals(input);
+            if !names.is_empty() {
+                if let Some(defined) = previewer.are_defined(&names) {
+                    highlighter.resolve_globals(names.into_iter().zip(defined));
+                }
+            }
+        }
+
+        let call = signature::enclosing_call(input, editor.cursor);
+        let callee = call.as_ref().map(|call| call.callee.as_str());
+        let finished = self.looking_up && !callee.is_some_and(signature::is_pending);
+        if callee != self.call.as_ref().map(|call| call.callee.as_str()) || finished {
+            self.signature = callee.and_then(|callee| signature::lookup(previewer, callee));
+            self.looking_up = callee.is_some_and(signature::is_pending);
+            changed = true;
+        }
+        if call != self.call {
+            self.call = call;
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Redraws the prompt and the input. Suggestions, marks and hints are only drawn while
+/// typing, which is when `live` is given. Hints about an outdated input are left out.
+fn draw(
+    prompt: &Prompt,
+    editor: &Editor,
+    highlighter: &mut Highlighter,
+    renderer: &mut Renderer,
+    live: Option<&Live>,
+) {
+    let Some(live) = live else {
+        let highlighted = highlighter.highlight(&editor.buffer, &[]);
+        renderer.render(&Frame {
+            prompt: &prompt.line,
+            right_prompt: &prompt.right,
+            text: &editor.buffer,
+            highlighted: &highlighted,
+            cursor: editor.buffer.len(),
+            ..Frame::default()
+        });
+        return;
+    };
+
+    let mut marks = editor.marks();
+    let mut hints = Vec::new();
+    if live.checked == editor.buffer {
+        if let (Some(Call { argument, .. }), Some(signature)) = (&live.call, &live.signature) {
+            hints.push(format!("{PREVIEW}{}", signature.hint(*argument)));
+        }
+        if let Some(SyntaxError { range, message }) = &live.syntax_error {
+            marks.push(Mark {
+                range: range.clone(),
+                escape: SYNTAX_ERROR,
+            });
+            hints.push(format!("{ERROR_MESSAGE}{message}"));
+        }
+        if let Some(preview) = &live.preview {
+            hints.push(format!("{PREVIEW}{preview}"));
+        }
+    }
+
+    let highlighted = highlighter.highlight(&editor.buffer, &marks);
+    let suggestion = editor.suggestion();
+    renderer.render(&Frame {
+        prompt: &prompt.line,
+        right_prompt: &prompt.right,
+        text: &editor.buffer,
+        highlighted: &highlighted,
+        suggestion: suggestion.as_deref(),
+        cursor: editor.cursor,
+        hints: &hints,
+    });
 }
 
 // fn is_valid_program(program: &str) -> bool {
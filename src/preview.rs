@@ -0,0 +1,251 @@
+use rusty_v8::{
+    inspector::{
+        ChannelBase, ChannelImpl, StringBuffer, StringView, V8Inspector, V8InspectorClientBase,
+        V8InspectorClientImpl, V8InspectorSession,
+    },
+    HandleScope, UniquePtr, UniqueRef,
+};
+use serde_json::{json, Value};
+
+const CONTEXT_GROUP_ID: i32 = 1;
+
+/// How long the preview may run before V8 stops it, in milliseconds.
+const TIMEOUT: u64 = 100;
+
+/// Longest preview shown, in characters.
+const MAX_LENGTH: usize = 200;
+
+struct Client {
+    base: V8InspectorClientBase,
+}
+
+impl V8InspectorClientImpl for Client {
+    fn base(&self) -> &V8InspectorClientBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut V8InspectorClientBase {
+        &mut self.base
+    }
+}
+
+/// Keeps the inspector's response to the last message. Evaluating is synchronous, so the
+/// response is there as soon as dispatching the message returns.
+struct Channel {
+    base: ChannelBase,
+    response: Option<String>,
+}
+
+impl ChannelImpl for Channel {
+    fn base(&self) -> &ChannelBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut ChannelBase {
+        &mut self.base
+    }
+
+    fn send_response(&mut self, _call_id: i32, mut message: UniquePtr<StringBuffer>) {
+        self.response = message.as_mut().map(|message| message.string().to_string());
+    }
+
+    fn send_notification(&mut self, _message: UniquePtr<StringBuffer>) {}
+
+    fn flush_protocol_notifications(&mut self) {}
+}
+
+/// Evaluates the input the way DevTools' eager evaluation does: through the inspector with
+/// `throwOnSideEffect`, so anything that would change state (including every native
+/// function not marked as side effect free, like the ones running executables) throws
+/// instead of running.
+pub struct Previewer {
+    // Fields drop in order, the session has to go before the inspector, and the inspector
+    // before the client and channel it points to.
+    session: UniqueRef<V8InspectorSession>,
+    _inspector: UniqueRef<V8Inspector>,
+    _client: Box<Client>,
+    channel: Box<Channel>,
+    next_id: i32,
+}
+
+impl Previewer {
+    pub fn new(scope: &mut HandleScope) -> Self {
+        let context = scope.get_current_context();
+
+        let mut client = Box::new(Client {
+            base: V8InspectorClientBase::new::<Client>(),
+        });
+        let mut inspector = V8Inspector::create(scope, &mut *client);
+        inspector.context_created(context, CONTEXT_GROUP_ID, StringView::from(&b"jssh"[..]));
+
+        let mut channel = Box::new(Channel {
+            base: ChannelBase::new::<Channel>(),
+            response: None,
+        });
+        let session = inspector.connect(CONTEXT_GROUP_ID, &mut *channel, StringView::empty());
+
+        Self {
+            session,
+            _inspector: inspector,
+            _client: client,
+            channel,
+            next_id: 0,
+        }
+    }
+
+    /// Returns a one line preview of what `source` evaluates to, or `None` if it throws,
+    /// has side effects, takes too long or evaluates to `undefined`.
+    pub fn preview(&mut self, source: &str) -> Option<String> {
+        if source.trim().is_empty() {
+            return None;
+        }
+
+        let preview = format_remote_object(&self.evaluate(source)?)?;
+        if preview == source.trim() {
+            return None;
+        }
+
+        let preview = preview.replace(['\n', '\r'], " ");
+        Some(match preview.char_indices().nth(MAX_LENGTH) {
+            Some((index, _)) => format!("{}…", &preview[..index]),
+            None => preview,
+        })
+    }
+
+    /// Returns the source code of the function `name` refers to, if it is one.
+    pub fn function_source(&mut self, name: &str) -> Option<String> {
+        let expression = format!(
+            "typeof {name} === 'function' ? Function.prototype.toString.call({name}) : undefined"
+        );
+        let result = self.evaluate(&expression)?;
+        Some(result.get("value")?.as_str()?.to_string())
+    }
+
+    /// Returns for each of `names` whether a variable by that name is defined.
+    pub fn are_defined(&mut self, names: &[String]) -> Option<Vec<bool>> {
+        // Variables in their temporal dead zone throw, but are defined as well.
+        let checks = names
+            .iter()
+            .map(|name| {
+                format!("(() => {{ try {{ return typeof {name} !== 'undefined' }} catch {{ return true }} }})()")
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let result = self.evaluate(&format!("JSON.stringify([{checks}])"))?;
+        serde_json::from_str(result.get("value")?.as_str()?).ok()
+    }
+
+    /// Evaluates `expression` without side effects and returns the resulting
+    /// `Runtime.RemoteObject`, unless it threw.
+    fn evaluate(&mut self, expression: &str) -> Option<Value> {
+        let response = self.send(
+            "Runtime.evaluate",
+            json!({
+                "expression": expression,
+                "throwOnSideEffect": true,
+                "timeout": TIMEOUT,
+                "replMode": true,
+                "silent": true,
+                "generatePreview": true,
+                "objectGroup": "preview",
+            }),
+        );
+        self.send(
+            "Runtime.releaseObjectGroup",
+            json!({ "objectGroup": "preview" }),
+        );
+
+        let mut response = response?;
+        let result = response.get_mut("result")?;
+        if result.get("exceptionDetails").is_some() {
+            return None;
+        }
+        Some(result.get_mut("result")?.take())
+    }
+
+    fn send(&mut self, method: &str, params: Value) -> Option<Value> {
+        self.next_id += 1;
+        let message = json!({ "id": self.next_id, "method": method, "params": params }).to_string();
+
+        self.channel.response = None;
+        self.session
+            .dispatch_protocol_message(StringView::from(message.as_bytes()));
+        serde_json::from_str(&self.channel.response.take()?).ok()
+    }
+}
+
+/// Formats a `Runtime.RemoteObject` similar to how DevTools shows it.
+fn format_remote_object(object: &Value) -> Option<String> {
+    let kind = object.get("type")?.as_str()?;
+    let description = object.get("description").and_then(Value::as_str);
+
+    match kind {
+        "undefined" => None,
+        "string" => Some(Value::String(object.get("value")?.as_str()?.to_string()).to_string()),
+        "function" => {
+            let signature = description?
+                .split(['{', '\n'])
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .trim_start_matches("function")
+                .trim();
+            Some(format!("ƒ {signature}"))
+        }
+        "object" if object.get("subtype").and_then(Value::as_str) == Some("null") => {
+            Some(String::from("null"))
+        }
+        "object" => match object.get("preview") {
+            Some(preview) => format_object_preview(preview, description),
+            None => description.map(str::to_string),
+        },
+        _ => description
+            .map(str::to_string)
+            .or_else(|| object.get("value").map(Value::to_string)),
+    }
+}
+
+fn format_object_preview(preview: &Value, description: Option<&str>) -> Option<String> {
+    let properties = preview.get("properties")?.as_array()?;
+    let overflow = preview.get("overflow").and_then(Value::as_bool) == Some(true);
+    let is_array = preview.get("subtype").and_then(Value::as_str) == Some("array");
+
+    let mut items = properties
+        .iter()
+        .map(|property| {
+            let name = property
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let value = match property.get("type").and_then(Value::as_str) {
+                Some("string") => Value::String(
+                    property
+                        .get("value")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                )
+                .to_string(),
+                _ => property
+                    .get("value")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+            match is_array {
+                true => value,
+                false => format!("{name}: {value}"),
+            }
+        })
+        .collect::<Vec<String>>();
+    if overflow {
+        items.push(String::from("…"));
+    }
+
+    let items = items.join(", ");
+    Some(match (is_array, description) {
+        (true, _) => format!("[{items}]"),
+        (false, Some("Object") | None) => format!("{{{items}}}"),
+        (false, Some(description)) => format!("{description} {{{items}}}"),
+    })
+}
@@ -0,0 +1,147 @@
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rusty_v8::{self as v8, HandleScope};
+use std::{env, time::Duration};
+
+use crate::{functions::set_property, git, highlight::ERROR_MESSAGE, io::NEWLINE_RETURN, terminal};
+
+lazy_static! {
+    pub static ref LAST_COMMAND: Mutex<LastCommand> = Mutex::new(LastCommand::default());
+}
+
+/// Used when there's no `$prompt` function or it throws.
+pub const DEFAULT_PROMPT: &str = "> ";
+
+/// Used instead of the default prompt after an input failed.
+const FAILED_PROMPT: &str = "\x1b[31m>\x1b[0m ";
+
+/// How the last input that was run went.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastCommand {
+    pub status: i32,
+    pub duration: Duration,
+}
+
+/// The prompts drawn around the input.
+#[derive(Debug, Clone, Default)]
+pub struct Prompt {
+    /// Lines of the prompt above the one the input starts on.
+    pub above: String,
+    /// The last line of the prompt, the input follows it.
+    pub line: String,
+    /// Right-aligned on the row the input starts on.
+    pub right: String,
+}
+
+impl Prompt {
+    /// Evaluates the user's `$prompt` and `$rprompt` functions, falling back to the
+    /// default prompt and no right prompt.
+    pub fn evaluate(scope: &mut HandleScope) -> Self {
+        let prompt = evaluate(scope, "$prompt").unwrap_or_else(|| {
+            match LAST_COMMAND.lock().status {
+                0 => DEFAULT_PROMPT,
+                _ => FAILED_PROMPT,
+            }
+            .to_string()
+        });
+        let (above, line) = match prompt.rsplit_once('\n') {
+            Some((above, line)) => (above.to_string(), line.to_string()),
+            None => (String::new(), prompt),
+        };
+        let right = evaluate(scope, "$rprompt")
+            .and_then(|right| right.lines().next().map(str::to_string))
+            .unwrap_or_default();
+        Self { above, line, right }
+    }
+
+    /// The compact form of the prompt submitted inputs are left with when
+    /// `transient_prompt` is on, from `$transientPrompt` or else the default prompt.
+    pub fn transient(scope: &mut HandleScope) -> Self {
+        Self {
+            line: evaluate(scope, "$transientPrompt").unwrap_or_else(|| DEFAULT_PROMPT.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Remembers how the last input went and exposes it as `$lastStatus` and `$lastDuration`
+/// (in milliseconds).
+pub fn record(scope: &mut HandleScope, last: LastCommand) {
+    *LAST_COMMAND.lock() = last;
+
+    let global = scope.get_current_context().global(scope);
+    let status = v8::Integer::new(scope, last.status).into();
+    set_property(scope, global, "$lastStatus", status);
+    let duration = v8::Number::new(scope, last.duration.as_secs_f64() * 1000.0).into();
+    set_property(scope, global, "$lastDuration", duration);
+}
+
+/// Formats a duration the way it's shown to users, like `850ms`, `6.2s` or `2m 5s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Calls the global function `name` with what a prompt might want to show and returns
+/// what it returned as a string. Prints what it threw, if anything.
+fn evaluate(scope: &mut HandleScope, name: &str) -> Option<String> {
+    let scope = &mut v8::TryCatch::new(scope);
+    let global = scope.get_current_context().global(scope);
+    let key = v8::String::new(scope, name)?.into();
+    let function = v8::Local::<v8::Function>::try_from(global.get(scope, key)?).ok()?;
+
+    let context = context(scope);
+    let undefined = v8::undefined(scope).into();
+    let result = function.call(scope, undefined, &[context.into()]);
+
+    if let Some(exception) = scope.exception() {
+        let message = exception.to_rust_string_lossy(scope);
+        let error = format!("{ERROR_MESSAGE}{name}: {message}\x1b[0m");
+        print!("{}{NEWLINE_RETURN}", terminal::styled(&error));
+        return None;
+    }
+    let result = result?;
+    match result.is_null_or_undefined() {
+        true => None,
+        false => Some(result.to_rust_string_lossy(scope)),
+    }
+}
+
+/// Builds `{ status, duration, cwd, git: { branch } }`, `git` being `null` outside of
+/// repositories and `duration` in milliseconds.
+fn context<'s>(scope: &mut HandleScope<'s>) -> v8::Local<'s, v8::Object> {
+    let last = *LAST_COMMAND.lock();
+    let cwd = env::current_dir().ok();
+
+    let context = v8::Object::new(scope);
+    let status = v8::Integer::new(scope, last.status).into();
+    set_property(scope, context, "status", status);
+    let duration = v8::Number::new(scope, last.duration.as_secs_f64() * 1000.0).into();
+    set_property(scope, context, "duration", duration);
+
+    let cwd_value = match &cwd {
+        Some(cwd) => v8::String::new(scope, &cwd.to_string_lossy())
+            .unwrap()
+            .into(),
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, context, "cwd", cwd_value);
+
+    let git = match cwd.as_deref().and_then(git::branch) {
+        Some(branch) => {
+            let git = v8::Object::new(scope);
+            let branch = v8::String::new(scope, &branch).unwrap().into();
+            set_property(scope, git, "branch", branch);
+            git.into()
+        }
+        None => v8::null(scope).into(),
+    };
+    set_property(scope, context, "git", git);
+
+    context
+}
@@ -0,0 +1,308 @@
+use crossterm::{
+    cursor::{MoveToColumn, MoveUp},
+    terminal::{self, Clear, ClearType},
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::Write;
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    highlight::SUGGESTION,
+    io::{flush, NEWLINE_RETURN},
+    terminal::styled,
+};
+
+lazy_static! {
+    static ref ESCAPE_SEQUENCE: Regex = Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").unwrap();
+}
+
+/// Number of columns `text` takes up in the terminal, ignoring ANSI escape sequences.
+pub fn visible_width(text: &str) -> usize {
+    ESCAPE_SEQUENCE
+        .replace_all(text, "")
+        .chars()
+        .map(|c| c.width().unwrap_or(0))
+        .sum()
+}
+
+/// A row and column on screen, relative to the first row of the prompt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Position {
+    row: usize,
+    column: usize,
+}
+
+/// Where the cursor and the end of the input land once the terminal soft-wraps them.
+struct Layout {
+    cursor: Position,
+    end: Position,
+}
+
+/// The input as it was last drawn, kept so it can be laid out again after a resize.
+#[derive(Default)]
+struct Drawn {
+    prompt_width: usize,
+    text: String,
+    cursor: usize,
+    hints: Vec<String>,
+}
+
+/// Everything drawn for one state of the line editor.
+#[derive(Default)]
+pub struct Frame<'a> {
+    pub prompt: &'a str,
+    /// Shown at the right edge of the first row until the input gets close to it.
+    pub right_prompt: &'a str,
+    pub text: &'a str,
+    pub highlighted: &'a str,
+    pub suggestion: Option<&'a str>,
+    /// Byte offset of the cursor in `text`.
+    pub cursor: usize,
+    /// Lines shown below the input, like syntax errors.
+    pub hints: &'a [String],
+}
+
+/// Draws the prompt and the input buffer, keeping track of how many rows it occupies so
+/// it can be redrawn in place even when it wraps past the terminal width.
+pub struct Renderer {
+    width: usize,
+    /// Row of the cursor relative to the first row of the prompt after the last render.
+    cursor_row: usize,
+    /// Row the input ended on after the last render.
+    end_row: usize,
+    /// Lines of the prompt printed above the first row.
+    above: String,
+    drawn: Drawn,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        let width = terminal::size().map_or(80, |(columns, _)| columns as usize);
+        Self {
+            width: width.max(1),
+            cursor_row: 0,
+            end_row: 0,
+            above: String::new(),
+            drawn: Drawn::default(),
+        }
+    }
+
+    /// Forgets about the previous input so the next render starts on the current row.
+    pub fn reset(&mut self) {
+        self.cursor_row = 0;
+        self.end_row = 0;
+        self.above = String::new();
+        self.drawn = Drawn::default();
+    }
+
+    /// Prints the lines of a multi-line prompt above the row the input starts on. They
+    /// aren't redrawn with the input, but are remembered so `collapse` can remove them.
+    pub fn print_above(&mut self, above: &str) {
+        if above.is_empty() {
+            return;
+        }
+        let above = above.replace('\n', NEWLINE_RETURN.as_str());
+        print!("{}{NEWLINE_RETURN}", styled(&above));
+        flush();
+        self.above = above.to_string();
+    }
+
+    /// Clears the input and the lines printed by `print_above`, so the next render
+    /// replaces them.
+    pub fn collapse(&mut self) {
+        let up = self.cursor_row + self.above_rows();
+        if up > 0 {
+            print!("{}", MoveUp(up as u16));
+        }
+        print!("\r{}", Clear(ClearType::FromCursorDown));
+        self.cursor_row = 0;
+        self.above = String::new();
+    }
+
+    /// Picks up a new terminal width. The terminal reflows what was drawn, so the cursor
+    /// row is recomputed for the new width before the next render moves back up.
+    pub fn resize(&mut self, columns: u16) {
+        self.width = (columns as usize).max(1);
+        let layout = self.layout(self.drawn.prompt_width, &self.drawn.text, self.drawn.cursor);
+        self.cursor_row = layout.cursor.row;
+        self.end_row = layout.end.row + self.hint_rows(&self.drawn.hints);
+    }
+
+    /// Redraws the prompt followed by the highlighted input, its dimmed suggestion, the
+    /// right prompt if there's room and the hints below it, then puts the cursor where it belongs in the input.
+    pub fn render(&mut self, frame: &Frame) {
+        let prompt_width = visible_width(frame.prompt);
+        let continuation = " ".repeat(self.indent(prompt_width));
+        let suggestion = frame.suggestion.unwrap_or_default();
+
+        let mut output = String::new();
+        if self.cursor_row > 0 {
+            write!(output, "{}", MoveUp(self.cursor_row as u16)).unwrap();
+        }
+        write!(
+            output,
+            "\r{}{}",
+            Clear(ClearType::FromCursorDown),
+            frame.prompt
+        )
+        .unwrap();
+
+        let full_text = format!("{}{suggestion}", frame.text);
+        let first_line = full_text.split('\n').next().unwrap_or_default();
+        if let Some(column) = self.right_prompt_column(prompt_width, first_line, frame.right_prompt)
+        {
+            write!(
+                output,
+                "{}{}\x1b[0m{}",
+                MoveToColumn(column),
+                frame.right_prompt,
+                MoveToColumn((prompt_width % self.width) as u16)
+            )
+            .unwrap();
+        }
+
+        let newline = format!("{NEWLINE_RETURN}{continuation}");
+        output.push_str(&frame.highlighted.replace('\n', &newline));
+        if !suggestion.is_empty() {
+            let suggestion = suggestion.replace('\n', &newline);
+            write!(output, "{SUGGESTION}{suggestion}\x1b[0m").unwrap();
+        }
+
+        let layout = self.layout(prompt_width, &full_text, frame.cursor);
+
+        // A row filled up to the last column leaves the terminal waiting to wrap, so the
+        // wrap is forced to keep the real cursor in line with the computed end.
+        if layout.end.column == 0 && layout.end.row > 0 && !full_text.ends_with('\n') {
+            write!(output, "{NEWLINE_RETURN}").unwrap();
+        }
+
+        for hint in frame.hints {
+            write!(output, "{NEWLINE_RETURN}{hint}\x1b[0m").unwrap();
+        }
+        let end_row = layout.end.row + self.hint_rows(frame.hints);
+
+        let up = end_row - layout.cursor.row;
+        if up > 0 {
+            write!(output, "{}", MoveUp(up as u16)).unwrap();
+        }
+        write!(output, "{}", MoveToColumn(layout.cursor.column as u16)).unwrap();
+
+        print!("{}", styled(&output));
+        flush();
+
+        self.cursor_row = layout.cursor.row;
+        self.end_row = end_row;
+        self.drawn = Drawn {
+            prompt_width,
+            text: full_text,
+            cursor: frame.cursor,
+            hints: frame.hints.to_vec(),
+        };
+    }
+
+    /// Number of rows the hints take up, each starting on a new row.
+    fn hint_rows(&self, hints: &[String]) -> usize {
+        hints
+            .iter()
+            .map(|hint| visible_width(hint).div_ceil(self.width).max(1))
+            .sum()
+    }
+
+    /// Number of rows the lines printed by `print_above` take up.
+    fn above_rows(&self) -> usize {
+        match self.above.is_empty() {
+            true => 0,
+            false => self
+                .above
+                .split('\n')
+                .map(|line| visible_width(line).div_ceil(self.width).max(1))
+                .sum(),
+        }
+    }
+
+    /// Column the right prompt starts at, leaving the last column free. There's none when
+    /// the first line of the input would come closer than a column to it.
+    fn right_prompt_column(
+        &self,
+        prompt_width: usize,
+        first_line: &str,
+        right_prompt: &str,
+    ) -> Option<u16> {
+        let width = visible_width(right_prompt);
+        if width == 0 {
+            return None;
+        }
+        let column = self.width.checked_sub(width + 1)?;
+        let end = self.layout(prompt_width, first_line, first_line.len()).end;
+        (end.row == prompt_width / self.width && end.column < column).then_some(column as u16)
+    }
+
+    /// Moves the cursor below the input, ready for the output of the submitted command.
+    pub fn finish(&mut self) {
+        let down = self.end_row - self.cursor_row;
+        print!("{}", NEWLINE_RETURN.repeat(down + 1));
+        flush();
+        self.reset();
+    }
+
+    fn layout(&self, prompt_width: usize, text: &str, cursor: usize) -> Layout {
+        let mut position = Position {
+            row: prompt_width / self.width,
+            column: prompt_width % self.width,
+        };
+        let mut cursor_position = None;
+
+        for (index, c) in text.char_indices() {
+            if index == cursor {
+                cursor_position = Some(self.wrap(position));
+            }
+
+            if c == '\n' {
+                position = Position {
+                    row: position.row + 1,
+                    column: self.indent(prompt_width),
+                };
+                continue;
+            }
+
+            let width = c.width().unwrap_or(0);
+            if position.column + width > self.width {
+                position = Position {
+                    row: position.row + 1,
+                    column: 0,
+                };
+            }
+            position.column += width;
+        }
+
+        let end = self.wrap(position);
+        Layout {
+            cursor: cursor_position.unwrap_or(end),
+            end,
+        }
+    }
+
+    /// Width of the continuation prompt that lines up later lines of the input with the first.
+    fn indent(&self, prompt_width: usize) -> usize {
+        prompt_width.min(self.width - 1)
+    }
+
+    /// Moves a position sitting right after the last column to the start of the next row.
+    fn wrap(&self, position: Position) -> Position {
+        if position.column >= self.width {
+            Position {
+                row: position.row + 1,
+                column: 0,
+            }
+        } else {
+            position
+        }
+    }
+}
@@ -1 +1,24 @@
 // This is the default config for JSSH
+
+// The prompt is whatever `$prompt` returns, it's called before every input with the
+// status and duration (in ms) of the last one, the cwd and the git branch.
+// globalThis.$prompt = ({ status, cwd, git }) =>
+//   `${status === 0 ? "" : "\x1b[31m"}${cwd.replace($HOME, "~")}${git ? ` (${git.branch})` : ""}\x1b[0m > `;
+
+// `$rprompt` is shown at the right edge of the input row until the input gets close to it.
+// globalThis.$rprompt = ({ duration }) => `\x1b[2m${Math.round(duration)}ms\x1b[0m`;
+
+// With `transient_prompt = true` in the config, submitted inputs are left with the shorter
+// `$transientPrompt` (or `> `) to keep the scrollback clean.
+// globalThis.$transientPrompt = () => "❯ ";
+
+// `$git.status()` returns the branch, ahead/behind counts and numbers of staged, modified,
+// untracked and conflicted files (or null outside of repositories). `git status` runs in the
+// background and the prompt is repainted once it's done, until then `pending` is true.
+// globalThis.$prompt = () => {
+//   const git = $git.status();
+//   return git ? `${git.branch}${git.dirty ? "*" : ""} > ` : "> ";
+// };
+
+// `$lastStatus` and `$lastDuration` (in ms) tell how the last input went. The status is the
+// exit status of the last executable it ran, or 10/11 when it failed to compile/threw.
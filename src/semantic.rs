@@ -0,0 +1,430 @@
+use std::{ops::Range, path::Path};
+
+use crate::functions::{BUILTIN_SIGNATURES, EXECUTABLES};
+
+const KEYWORDS: [&str; 48] = [
+    "arguments",
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "get",
+    "if",
+    "import",
+    "in",
+    "Infinity",
+    "instanceof",
+    "let",
+    "NaN",
+    "new",
+    "null",
+    "of",
+    "return",
+    "set",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// What an identifier in the input refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Executable,
+    Builtin,
+    Global,
+    /// Called without being declared anywhere.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Identifier(Range<usize>),
+    /// A string literal, `Range` covering its quotes.
+    String(Range<usize>, String),
+    Punctuation(usize, char),
+}
+
+/// A reference to a variable in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub range: Range<usize>,
+    pub called: bool,
+}
+
+/// What the semantic pass found in a line, with byte ranges relative to the line.
+#[derive(Debug, Clone, Default)]
+pub struct LineAnalysis {
+    pub references: Vec<Reference>,
+    /// Names declared in the line, which aren't globals yet.
+    pub declared: Vec<String>,
+    /// String literals and their contents.
+    pub strings: Vec<(Range<usize>, String)>,
+}
+
+/// What the identifiers being read are declaring.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Declaring {
+    #[default]
+    Nothing,
+    /// Names after `let`, `const`, `var`, `class` and `function` at bracket `depth`,
+    /// `named` once one was seen outside of destructuring brackets.
+    Bindings { depth: usize, named: bool },
+    /// Parameters of a function or `catch`, until the bracket at `depth` closes.
+    Parameters { depth: usize },
+}
+
+/// An open bracket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bracket {
+    c: char,
+    /// Offset of the bracket in the line being analysed, if it's in it.
+    start: Option<usize>,
+    /// Names referenced directly inside a `(`, which are parameters if `=>` follows it.
+    names: Vec<String>,
+}
+
+/// What was right before the next token, for telling property names and object keys
+/// apart from variables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Previous {
+    #[default]
+    Other,
+    /// A number of `.` in a row, one accessing a property and three spreading.
+    Dots(usize),
+    /// A `{` or `,`, which an object key follows.
+    KeyStart,
+}
+
+/// Where the analysis is at the end of a line: everything needed to analyse the next line
+/// on its own. Lines after an edit don't have to be analysed again if it's unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct State {
+    /// Brace depth of each `${` the tokenizer is in.
+    templates: Vec<usize>,
+    depth: usize,
+    in_template_text: bool,
+    in_comment: bool,
+    declaring: Declaring,
+    brackets: Vec<Bracket>,
+    previous: Previous,
+}
+
+/// Finds the variables referenced, the ones declared and the string literals in `line`,
+/// skipping comments, keywords, property names and the text of template literals.
+/// `state` is where the previous line left off and is moved past `line`.
+pub fn analyze_line(line: &str, state: &mut State) -> LineAnalysis {
+    let tokens = tokenize(line, state);
+    let mut analysis = LineAnalysis::default();
+
+    for (index, token) in tokens.iter().enumerate() {
+        let next = tokens.get(index + 1);
+        let depth = state.brackets.len();
+        let previous = state.previous;
+        state.previous = match token {
+            Token::Punctuation(_, '.') => match previous {
+                Previous::Dots(dots) => Previous::Dots(dots + 1),
+                _ => Previous::Dots(1),
+            },
+            Token::Punctuation(_, '{' | ',') => Previous::KeyStart,
+            _ => Previous::Other,
+        };
+
+        match (token, &state.declaring) {
+            (Token::Punctuation(at, '('), Declaring::Bindings { depth: bound, .. })
+                if *bound == depth =>
+            {
+                state.brackets.push(Bracket::new('(', *at));
+                state.declaring = Declaring::Parameters { depth: depth + 1 };
+            }
+            (
+                Token::Punctuation(at, c @ ('[' | '{')),
+                Declaring::Bindings {
+                    depth: bound,
+                    named: true,
+                },
+            ) if *bound == depth => {
+                state.brackets.push(Bracket::new(*c, *at));
+                state.declaring = Declaring::Nothing;
+            }
+            (Token::Punctuation(at, c @ ('(' | '[' | '{')), _) => {
+                state.brackets.push(Bracket::new(*c, *at))
+            }
+            (Token::Punctuation(at, c @ (')' | ']' | '}')), _) => {
+                if matches!(state.declaring, Declaring::Parameters { depth: bound } if bound == depth)
+                {
+                    state.declaring = Declaring::Nothing;
+                }
+                let Some(bracket) = state.brackets.pop() else {
+                    continue;
+                };
+                // What looked like references inside parentheses followed by `=>` were
+                // the parameters of an arrow function. A line break isn't allowed before
+                // the `=>`, so it's always on this line.
+                let is_arrow = bracket.c == '(' && *c == ')';
+                if is_arrow && line[at + 1..].trim_start().starts_with("=>") {
+                    if let Some(start) = bracket.start {
+                        analysis
+                            .references
+                            .retain(|reference| reference.range.start < start);
+                    }
+                    analysis.declared.extend(bracket.names);
+                }
+            }
+            (Token::Punctuation(_, '=' | ';'), Declaring::Bindings { depth: bound, .. })
+                if *bound == depth =>
+            {
+                state.declaring = Declaring::Nothing;
+            }
+            (Token::String(range, content), _) => {
+                analysis.strings.push((range.clone(), content.clone()))
+            }
+            (Token::Identifier(range), _) => {
+                let name = &line[range.clone()];
+                match name {
+                    "let" | "const" | "var" | "class" | "function" => {
+                        state.declaring = Declaring::Bindings {
+                            depth,
+                            named: false,
+                        };
+                        continue;
+                    }
+                    "catch" => {
+                        state.declaring = Declaring::Bindings { depth, named: true };
+                        continue;
+                    }
+                    "in" | "of" => state.declaring = Declaring::Nothing,
+                    _ => (),
+                }
+                if KEYWORDS.contains(&name) {
+                    continue;
+                }
+
+                let is_parameter = matches!(next, Some(Token::Punctuation(at, '=')) if line[*at..].starts_with("=>"));
+                let is_declared = match &mut state.declaring {
+                    Declaring::Bindings { named, .. } => {
+                        *named = true;
+                        true
+                    }
+                    Declaring::Parameters { .. } => true,
+                    Declaring::Nothing => is_parameter,
+                };
+                if is_declared {
+                    analysis.declared.push(name.to_string());
+                    continue;
+                }
+
+                let is_property = previous == Previous::Dots(1);
+                let is_key = matches!(next, Some(Token::Punctuation(_, ':')))
+                    && previous == Previous::KeyStart;
+                if is_property || is_key {
+                    continue;
+                }
+                analysis.references.push(Reference {
+                    range: range.clone(),
+                    called: matches!(next, Some(Token::Punctuation(_, '('))),
+                });
+                // Parentheses going over several lines remember the names, as the
+                // references on earlier lines are already analysed once `=>` shows up.
+                if let Some(bracket) = state.brackets.last_mut() {
+                    if bracket.c == '(' {
+                        bracket.names.push(name.to_string());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    for bracket in &mut state.brackets {
+        bracket.start = None;
+    }
+    analysis
+}
+
+impl Bracket {
+    fn new(c: char, start: usize) -> Self {
+        Self {
+            c,
+            start: Some(start),
+            names: Vec::new(),
+        }
+    }
+}
+
+/// Splits `line` into identifiers, string literals and punctuation, starting in `state`.
+/// The text parts of template literals are skipped, their `${}` expressions are
+/// tokenized like the rest.
+fn tokenize(line: &str, state: &mut State) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        if state.in_comment {
+            if c == '*' && next == Some('/') {
+                chars.next();
+                state.in_comment = false;
+            }
+            continue;
+        }
+        if state.in_template_text {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '`' => state.in_template_text = false,
+                '$' if next == Some('{') => {
+                    chars.next();
+                    state.in_template_text = false;
+                    state.depth += 1;
+                    state.templates.push(state.depth);
+                }
+                _ => (),
+            }
+            continue;
+        }
+
+        match c {
+            '/' if next == Some('/') => while chars.next_if(|&(_, c)| c != '\n').is_some() {},
+            '/' if next == Some('*') => {
+                chars.next();
+                state.in_comment = true;
+            }
+            '"' | '\'' => {
+                let mut content = String::new();
+                let mut end = line.len();
+                while let Some((at, inner)) = chars.next() {
+                    match inner {
+                        '\\' => content.extend(chars.next().map(|(_, escaped)| escaped)),
+                        _ if inner == c => {
+                            end = at + 1;
+                            break;
+                        }
+                        '\n' => {
+                            end = at;
+                            break;
+                        }
+                        _ => content.push(inner),
+                    }
+                }
+                tokens.push(Token::String(index..end, content));
+            }
+            '`' => state.in_template_text = true,
+            '{' => {
+                state.depth += 1;
+                tokens.push(Token::Punctuation(index, c));
+            }
+            '}' if state.templates.last() == Some(&state.depth) => {
+                state.templates.pop();
+                state.depth -= 1;
+                state.in_template_text = true;
+            }
+            '}' => {
+                state.depth = state.depth.saturating_sub(1);
+                tokens.push(Token::Punctuation(index, c));
+            }
+            c if is_identifier_start(c) => {
+                let mut end = index + c.len_utf8();
+                while let Some((at, c)) = chars.next_if(|&(_, c)| is_identifier_part(c)) {
+                    end = at + c.len_utf8();
+                }
+                tokens.push(Token::Identifier(index..end));
+            }
+            c if c.is_ascii_digit() => {
+                while chars
+                    .next_if(|&(_, c)| is_identifier_part(c) || c == '.')
+                    .is_some()
+                {}
+            }
+            c if c.is_whitespace() => (),
+            c => tokens.push(Token::Punctuation(index, c)),
+        }
+    }
+
+    tokens
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Whether `name` is one of the functions jssh defines, `$history` or `cd` for example.
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN_SIGNATURES
+        .iter()
+        .any(|builtin| builtin.name.split('.').next() == Some(name))
+}
+
+/// What `name` refers to, as far as it can be told without asking V8.
+pub fn resolve(name: &str) -> Option<Kind> {
+    if is_builtin(name) {
+        Some(Kind::Builtin)
+    } else if EXECUTABLES.lock().binary_search(&name.to_string()).is_ok() {
+        Some(Kind::Executable)
+    } else {
+        None
+    }
+}
+
+/// Whether a string literal names a file or directory that exists. Strings using
+/// variables that aren't set, like `"cost: $price"`, don't.
+pub fn is_existing_path(content: &str) -> bool {
+    if content.is_empty() || content.contains('\n') {
+        return false;
+    }
+    match shellexpand::full(content) {
+        Ok(path) => Path::new(path.as_ref()).exists(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_exist() {
+        assert!(is_existing_path("/"));
+        assert!(is_existing_path("~"));
+        assert!(!is_existing_path(""));
+        assert!(!is_existing_path("/no/such/file"));
+    }
+
+    #[test]
+    fn undefined_variables_are_not_paths() {
+        assert!(!is_existing_path("cost: $JSSH_UNDEFINED_PRICE"));
+        assert!(!is_existing_path("echo $JSSH_UNDEFINED_VARIABLE"));
+        assert!(!is_existing_path("${JSSH_UNDEFINED_DIRECTORY}/file"));
+    }
+}
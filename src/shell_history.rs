@@ -0,0 +1,292 @@
+use std::str::FromStr;
+
+use crate::history::Entry;
+
+/// The Meta byte zsh escapes bytes it uses internally with in its history file.
+const ZSH_META: u8 = 0x83;
+
+/// History file formats of other shells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One command per line, each preceded by a `#<timestamp>` line with `HISTTIMEFORMAT` set.
+    Bash,
+    /// `: <start>:<duration>;<command>` lines with `EXTENDED_HISTORY`, plain lines without.
+    Zsh,
+    /// Fish's YAML-like `- cmd: <command>` entries.
+    Fish,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "bash" => Ok(Format::Bash),
+            "zsh" => Ok(Format::Zsh),
+            "fish" => Ok(Format::Fish),
+            _ => Err(anyhow::anyhow!(
+                "unknown history format `{format}`, expected bash, zsh or fish"
+            )),
+        }
+    }
+}
+
+/// Reads the entries of a history file in `format`, oldest first.
+pub fn parse(format: Format, contents: &[u8]) -> Vec<Entry> {
+    match format {
+        Format::Bash => parse_bash(&String::from_utf8_lossy(contents)),
+        Format::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(contents))),
+        Format::Fish => parse_fish(&String::from_utf8_lossy(contents)),
+    }
+}
+
+/// Writes `entries` as a history file in `format`.
+pub fn format(format: Format, entries: &[Entry]) -> Vec<u8> {
+    let mut contents = String::new();
+    for entry in entries {
+        match format {
+            Format::Bash => {
+                if let Some(start) = entry.start {
+                    contents.push_str(&format!("#{start}\n"));
+                }
+                contents.push_str(&entry.command);
+            }
+            Format::Zsh => {
+                let command = entry.command.replace('\n', "\\\n");
+                match entry.start {
+                    Some(start) => {
+                        let duration = entry.duration.unwrap_or(0) / 1000;
+                        contents.push_str(&format!(": {start}:{duration};{command}"));
+                    }
+                    None => contents.push_str(&command),
+                }
+            }
+            Format::Fish => {
+                let command = entry.command.replace('\\', "\\\\").replace('\n', "\\n");
+                contents.push_str(&format!("- cmd: {command}"));
+                if let Some(start) = entry.start {
+                    contents.push_str(&format!("\n  when: {start}"));
+                }
+            }
+        }
+        contents.push('\n');
+    }
+
+    match format {
+        Format::Zsh => metafy(contents.as_bytes()),
+        _ => contents.into_bytes(),
+    }
+}
+
+/// The lines after a `#<timestamp>` line up to the next one are a single command, which
+/// keeps multi-line commands together. Lines before the first timestamp, like those saved
+/// before `HISTTIMEFORMAT` was set, are a command each.
+fn parse_bash(contents: &str) -> Vec<Entry> {
+    let timestamp = |line: &str| {
+        line.strip_prefix('#')
+            .filter(|digits| !digits.is_empty())
+            .and_then(|digits| digits.parse::<u64>().ok())
+    };
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut start = None;
+    let mut lines: Vec<&str> = Vec::new();
+    let mut flush = |start: Option<u64>, lines: &mut Vec<&str>| {
+        let command = lines.join("\n");
+        lines.clear();
+        if !command.trim().is_empty() {
+            entries.push(Entry {
+                start,
+                ..Entry::new(&command)
+            });
+        }
+    };
+    for line in contents.lines() {
+        match timestamp(line) {
+            Some(next) => {
+                flush(start, &mut lines);
+                start = Some(next);
+            }
+            None => {
+                lines.push(line);
+                if start.is_none() {
+                    flush(start, &mut lines);
+                }
+            }
+        }
+    }
+    flush(start, &mut lines);
+    entries
+}
+
+/// Lines ending in a backslash continue on the next one.
+fn parse_zsh(contents: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut lines = contents.lines();
+
+    while let Some(line) = lines.next() {
+        let mut command = line.to_string();
+        while command.ends_with('\\') {
+            let Some(next) = lines.next() else {
+                break;
+            };
+            command.pop();
+            command.push('\n');
+            command.push_str(next);
+        }
+
+        let extended = command
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+            .and_then(|(metadata, command)| {
+                let (start, duration) = metadata.split_once(':')?;
+                Some((
+                    start.parse::<u64>().ok()?,
+                    duration.parse::<u64>().ok()?,
+                    command,
+                ))
+            });
+        let entry = match extended {
+            Some((start, duration, command)) => Entry {
+                start: Some(start),
+                duration: Some(duration * 1000),
+                ..Entry::new(command)
+            },
+            None => Entry::new(&command),
+        };
+        if !entry.command.trim().is_empty() {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+fn parse_fish(contents: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for line in contents.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push(Entry::new(&unescape_fish(command)));
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+            if let Some(entry) = entries.last_mut() {
+                entry.start = when.trim().parse().ok();
+            }
+        }
+    }
+    entries
+}
+
+fn unescape_fish(command: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                unescaped.push('\\');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Whether zsh escapes `byte` in its history file: NUL, Meta and its tokens.
+fn is_meta(byte: u8) -> bool {
+    byte == 0 || (ZSH_META..=0xa2).contains(&byte)
+}
+
+fn metafy(bytes: &[u8]) -> Vec<u8> {
+    let mut metafied = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        if is_meta(byte) {
+            metafied.push(ZSH_META);
+            metafied.push(byte ^ 0x20);
+        } else {
+            metafied.push(byte);
+        }
+    }
+    metafied
+}
+
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut unmetafied = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            ZSH_META => unmetafied.extend(bytes.next().map(|next| next ^ 0x20)),
+            _ => unmetafied.push(byte),
+        }
+    }
+    unmetafied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, start: Option<u64>, duration: Option<u64>) -> Entry {
+        Entry {
+            start,
+            duration,
+            ..Entry::new(command)
+        }
+    }
+
+    #[test]
+    fn bash_keeps_untimestamped_lines_apart() {
+        let contents = "ls\npwd\n#1700000000\nfor x in a b; do\n  echo $x\ndone\n#1700000060\ncd\n";
+        assert_eq!(
+            parse(Format::Bash, contents.as_bytes()),
+            [
+                entry("ls", None, None),
+                entry("pwd", None, None),
+                entry("for x in a b; do\n  echo $x\ndone", Some(1700000000), None),
+                entry("cd", Some(1700000060), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn bash_round_trip() {
+        let entries = [
+            entry("ls", None, None),
+            entry("git status", None, None),
+            entry("if (x) {\n  y()\n}", Some(1700000000), None),
+            entry("pwd", Some(1700000001), None),
+        ];
+        let contents = format(Format::Bash, &entries);
+        assert_eq!(parse(Format::Bash, &contents), entries);
+    }
+
+    #[test]
+    fn zsh_round_trip() {
+        let entries = [
+            entry("echo plain", None, None),
+            entry("sleep 2", Some(1700000000), Some(2000)),
+            entry("echo 'a\nb'", Some(1700000010), Some(0)),
+            entry("echo ść", Some(1700000020), Some(0)),
+        ];
+        let contents = format(Format::Zsh, &entries);
+        assert_eq!(parse(Format::Zsh, &contents), entries);
+    }
+
+    #[test]
+    fn zsh_metafies_special_bytes() {
+        let contents = format(Format::Zsh, &[entry("ś", None, None)]);
+        assert_eq!(contents, [0xc5, ZSH_META, 0x9b ^ 0x20, b'\n']);
+    }
+
+    #[test]
+    fn fish_round_trip() {
+        let entries = [
+            entry("echo \\n", None, None),
+            entry("for x in a b\n  echo $x\nend", Some(1700000000), None),
+        ];
+        let contents = format(Format::Fish, &entries);
+        assert_eq!(parse(Format::Fish, &contents), entries);
+    }
+}
@@ -0,0 +1,286 @@
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    brackets,
+    config::CONFIG,
+    functions::{BUILTIN_SIGNATURES, EXECUTABLES},
+    preview::Previewer,
+};
+
+lazy_static! {
+    /// Summaries of `--help` for executables, `None` while the lookup is still running.
+    static ref HELP: Mutex<HashMap<String, Option<Option<String>>>> = Mutex::new(HashMap::new());
+}
+
+/// How long an executable may take to print its `--help`.
+const HELP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Words that are followed by a `(` without being calls.
+const KEYWORDS: [&str; 9] = [
+    "if", "for", "while", "switch", "catch", "function", "return", "typeof", "await",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub description: Option<String>,
+}
+
+impl Signature {
+    /// Formats the signature for the hint line with the parameter at `argument` underlined.
+    pub fn hint(&self, argument: usize) -> String {
+        let parameters = self
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(index, parameter)| {
+                let is_rest = parameter.starts_with("...");
+                match index == argument || (is_rest && index < argument) {
+                    true => format!("\x1b[4m{parameter}\x1b[24m"),
+                    false => parameter.to_string(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        match &self.description {
+            Some(description) => format!("{}({parameters}) — {description}", self.name),
+            None => format!("{}({parameters})", self.name),
+        }
+    }
+}
+
+/// The call the cursor is in: the name of the called function and the argument index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub callee: String,
+    pub argument: usize,
+}
+
+/// Finds the call around byte offset `cursor` of `source`, if it calls something by name.
+pub fn enclosing_call(source: &str, cursor: usize) -> Option<Call> {
+    let (parenthesis, argument) = brackets::enclosing_call(source, cursor)?;
+
+    let before = source[..parenthesis].trim_end();
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+        .map_or(0, |index| index + 1);
+    let callee = before[start..].trim_matches('.');
+
+    let starts_like_identifier = callee.chars().next().is_some_and(|c| !c.is_ascii_digit());
+    if !starts_like_identifier || KEYWORDS.contains(&callee) {
+        return None;
+    }
+
+    Some(Call {
+        callee: callee.to_string(),
+        argument,
+    })
+}
+
+/// Looks up the signature of `callee`: builtins come from their registry, executables from
+/// their `--help` and JavaScript functions from their source.
+///
+/// Returns `None` when nothing is known (yet), `--help` is looked up in the background.
+pub fn lookup(previewer: &mut Previewer, callee: &str) -> Option<Signature> {
+    if let Some(builtin) = BUILTIN_SIGNATURES
+        .iter()
+        .find(|builtin| builtin.name == callee)
+    {
+        return Some(Signature {
+            name: callee.to_string(),
+            parameters: split_parameters(builtin.parameters),
+            description: Some(builtin.description.to_string()),
+        });
+    }
+
+    if EXECUTABLES
+        .lock()
+        .binary_search(&callee.to_string())
+        .is_ok()
+    {
+        return executable_help(callee).map(|help| Signature {
+            name: callee.to_string(),
+            parameters: vec![String::from("...args")],
+            description: help,
+        });
+    }
+
+    let source = previewer.function_source(callee)?;
+    Some(Signature {
+        name: callee.to_string(),
+        parameters: split_parameters(&parameter_list(&source)?),
+        description: None,
+    })
+}
+
+/// Whether the `--help` of `callee` is still being looked up.
+pub fn is_pending(callee: &str) -> bool {
+    matches!(HELP.lock().get(callee), Some(None))
+}
+
+/// Returns the summary of `name --help` if it's known, `Some(None)` if there isn't one.
+fn executable_help(name: &str) -> Option<Option<String>> {
+    if !CONFIG.lock().executable_help {
+        return Some(None);
+    }
+
+    let mut help = HELP.lock();
+    if let Some(summary) = help.get(name) {
+        return summary.clone();
+    }
+    help.insert(name.to_string(), None);
+
+    let name = name.to_string();
+    thread::spawn(move || {
+        let summary = run_help(&name);
+        HELP.lock().insert(name, Some(summary));
+    });
+    None
+}
+
+/// Runs `name --help` and picks its usage line, or else its first line.
+fn run_help(name: &str) -> Option<String> {
+    let mut child = Command::new(name)
+        .arg("--help")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let started = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if started.elapsed() > HELP_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let output = child.wait_with_output().ok()?;
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>();
+
+    lines
+        .iter()
+        .find(|line| line.to_lowercase().starts_with("usage"))
+        .or(lines.first())
+        .map(|line| line.to_string())
+}
+
+/// Extracts the parameter list from the source of a function.
+fn parameter_list(source: &str) -> Option<String> {
+    let source = source.trim();
+
+    // Arrow functions with a single parameter don't need parentheses. Anything else before
+    // the `=>` means it belongs to an arrow function in the body.
+    if let Some((parameter, _)) = source.split_once("=>") {
+        let parameter = parameter.trim();
+        let parameter = match parameter.strip_prefix("async") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+            _ => parameter,
+        };
+        if is_identifier(parameter) {
+            return Some(parameter.to_string());
+        }
+    }
+
+    let open = source.find('(')?;
+    let (_, close) = brackets::balance(source)
+        .pairs
+        .into_iter()
+        .find(|&(start, _)| start == open)?;
+    Some(source[open + 1..close].to_string())
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => {
+            (first.is_alphabetic() || first == '_' || first == '$')
+                && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        }
+        None => false,
+    }
+}
+
+/// Splits a parameter list at its top level commas.
+fn split_parameters(list: &str) -> Vec<String> {
+    let mut parameters = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+
+    brackets::for_each_bracket(list, |index, c| match c {
+        ',' if depth == 0 => {
+            parameters.push(list[start..index].trim().to_string());
+            start = index + 1;
+        }
+        '(' | '[' | '{' => depth += 1,
+        ')' | ']' | '}' => depth -= 1,
+        _ => (),
+    });
+    parameters.push(list[start..].trim().to_string());
+
+    parameters.retain(|parameter| !parameter.is_empty());
+    parameters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_parameter_lists() {
+        assert_eq!(
+            parameter_list("function f(a, b) {}").as_deref(),
+            Some("a, b")
+        );
+        assert_eq!(
+            parameter_list("(a, { b }) => a").as_deref(),
+            Some("a, { b }")
+        );
+        assert_eq!(parameter_list("x => x * 2").as_deref(), Some("x"));
+        assert_eq!(
+            parameter_list("async value => value").as_deref(),
+            Some("value")
+        );
+        assert_eq!(
+            parameter_list("asyncValue => 1").as_deref(),
+            Some("asyncValue")
+        );
+    }
+
+    #[test]
+    fn arrows_in_the_body_are_not_parameters() {
+        assert_eq!(
+            parameter_list("function map(list, f) { return list.map(x => f(x)) }").as_deref(),
+            Some("list, f")
+        );
+        assert_eq!(
+            parameter_list("(a, b = () => 1) => a").as_deref(),
+            Some("a, b = () => 1")
+        );
+        assert_eq!(
+            parameter_list("async (a) => { await a }").as_deref(),
+            Some("a")
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use rusty_v8::{self as v8, HandleScope};
+use std::ops::Range;
+
+use crate::{brackets, editor::byte_offset};
+
+/// A syntax error V8 found in the input, located by byte offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub range: Range<usize>,
+    pub message: String,
+}
+
+/// Compiles `source` without running it and returns the syntax error in it, if any.
+///
+/// Errors that only mean the input isn't finished yet, like an unclosed bracket or string
+/// at the end, are not reported.
+pub fn check(scope: &mut HandleScope, source: &str) -> Option<SyntaxError> {
+    if source.trim().is_empty() || !brackets::ends_in_code(source) {
+        return None;
+    }
+
+    let scope = &mut v8::HandleScope::new(scope);
+    let scope = &mut v8::TryCatch::new(scope);
+
+    let code = v8::String::new(scope, source)?;
+    if v8::Script::compile(scope, code, None).is_some() {
+        return None;
+    }
+
+    let message = scope.message()?;
+    let start = byte_offset(source, message.get_start_position().max(0) as usize);
+    let end = byte_offset(source, message.get_end_position().max(0) as usize);
+    if start >= source.trim_end().len() {
+        return None;
+    }
+
+    // Point at least at the character the error starts at.
+    let end = match end > start {
+        true => end,
+        false => source[start..]
+            .chars()
+            .next()
+            .map_or(start, |c| start + c.len_utf8()),
+    };
+
+    Some(SyntaxError {
+        range: start..end,
+        message: message.get(scope).to_rust_string_lossy(scope),
+    })
+}
@@ -0,0 +1,136 @@
+use crossterm::{
+    event::{
+        DisableBracketedPaste, EnableBracketedPaste, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, terminal,
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use regex::{Captures, Regex};
+use std::{
+    borrow::Cow,
+    env,
+    io::{self, stdout, IsTerminal},
+};
+
+lazy_static! {
+    /// What jssh's input and output are connected to, worked out once at startup.
+    pub static ref TERMINAL: Terminal = Terminal::detect();
+    /// Whether the keyboard enhancement flags were pushed and have to be popped again.
+    static ref KEYBOARD_ENHANCED: Mutex<bool> = Mutex::new(false);
+    static ref STYLE_SEQUENCE: Regex = Regex::new(r"\x1b\[([0-9;:]*)m").unwrap();
+}
+
+pub struct Terminal {
+    /// Whether input comes from and output goes to a terminal that can move the cursor,
+    /// which the line editor needs. Otherwise input is read line by line.
+    pub interactive: bool,
+    /// Whether output may be coloured, which `NO_COLOR` turns off. Styles like bold or
+    /// underline are still drawn without colours.
+    pub color: bool,
+}
+
+impl Terminal {
+    /// Goes by whether stdin and stdout are terminals, `TERM=dumb` and `NO_COLOR`.
+    fn detect() -> Self {
+        let dumb = env::var("TERM").is_ok_and(|term| term == "dumb");
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let interactive = io::stdin().is_terminal() && stdout().is_terminal() && !dumb;
+        Self {
+            interactive,
+            color: interactive && !no_color,
+        }
+    }
+}
+
+/// Sets the terminal up for the line editor: raw mode, bracketed paste and, where the
+/// terminal supports it, unambiguous escape codes for keys like Ctrl-I and Tab.
+pub fn init() -> io::Result<()> {
+    if !TERMINAL.interactive {
+        return Ok(());
+    }
+    enable_raw_mode()?;
+    let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced {
+        execute!(
+            stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
+    *KEYBOARD_ENHANCED.lock() = enhanced;
+    execute!(stdout(), EnableBracketedPaste)
+}
+
+/// Undoes `init`.
+pub fn de_init() -> io::Result<()> {
+    if !TERMINAL.interactive {
+        return Ok(());
+    }
+    disable_raw_mode()?;
+    if std::mem::take(&mut *KEYBOARD_ENHANCED.lock()) {
+        execute!(stdout(), PopKeyboardEnhancementFlags)?;
+    }
+    execute!(stdout(), DisableBracketedPaste)
+}
+
+/// Switches to raw mode, unless input is read line by line.
+pub fn enable_raw_mode() -> io::Result<()> {
+    match TERMINAL.interactive {
+        true => terminal::enable_raw_mode(),
+        false => Ok(()),
+    }
+}
+
+/// Leaves raw mode, for example to hand the terminal to an executable.
+pub fn disable_raw_mode() -> io::Result<()> {
+    match TERMINAL.interactive {
+        true => terminal::disable_raw_mode(),
+        false => Ok(()),
+    }
+}
+
+/// Removes the colours from `text` when they're turned off, keeping styles like bold or
+/// underline, and every style when the output isn't an interactive terminal.
+pub fn styled(text: &str) -> Cow<'_, str> {
+    if !TERMINAL.interactive {
+        return STYLE_SEQUENCE.replace_all(text, "");
+    }
+    if TERMINAL.color {
+        return Cow::Borrowed(text);
+    }
+    STYLE_SEQUENCE.replace_all(text, |captures: &Captures| {
+        let kept = without_colors(&captures[1]);
+        match kept.is_empty() {
+            true => String::new(),
+            false => format!("\x1b[{}m", kept.join(";")),
+        }
+    })
+}
+
+/// The SGR parameters that don't set a foreground, background or underline colour.
+fn without_colors(parameters: &str) -> Vec<&str> {
+    let mut kept = Vec::new();
+    let mut parameters = parameters.split(';');
+    while let Some(parameter) = parameters.next() {
+        match parameter.parse::<u8>() {
+            // 256 colours take one more parameter and RGB colours three.
+            Ok(38 | 48 | 58) => match parameters.next() {
+                Some("5") => {
+                    parameters.next();
+                }
+                Some("2") => {
+                    parameters.nth(2);
+                }
+                _ => (),
+            },
+            Ok(30..=37 | 39 | 40..=47 | 49 | 59 | 90..=97 | 100..=107) => (),
+            // Colours with colon separated parameters, like `38:2::255:0:0`.
+            _ if ["38:", "48:", "58:"]
+                .iter()
+                .any(|prefix| parameter.starts_with(prefix)) => {}
+            _ => kept.push(parameter),
+        }
+    }
+    kept
+}
@@ -0,0 +1,83 @@
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use syntect::highlighting::{Theme, ThemeSet};
+
+use crate::{config::CONFIG, io::NEWLINE_RETURN, path::expand_path};
+
+lazy_static! {
+    static ref BUILTIN_THEMES: ThemeSet = ThemeSet::load_defaults();
+    /// The theme the input is highlighted with.
+    pub static ref THEME: Mutex<Theme> = Mutex::new(match load(&CONFIG.lock().theme) {
+        Ok(theme) => theme,
+        Err(error) => {
+            eprint!("Couldn't load the theme: {error}{NEWLINE_RETURN}");
+            default_theme()
+        }
+    });
+}
+
+/// Counts how often the theme was switched, so highlighting cached with an older theme can
+/// be told apart.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Name of the theme bundled with jssh, which uses the terminal's palette.
+pub const DEFAULT_THEME: &str = "default";
+
+fn default_theme() -> Theme {
+    serde_json::from_str(include_str!("../theme.json")).expect("the bundled theme is valid")
+}
+
+/// Names of the themes that can be loaded without a path.
+pub fn list() -> Vec<String> {
+    let mut names = vec![DEFAULT_THEME.to_string()];
+    names.extend(BUILTIN_THEMES.themes.keys().cloned());
+    names
+}
+
+/// Loads a theme by its name in `list` or from a path to a `.tmTheme` or JSON theme.
+pub fn load(name: &str) -> anyhow::Result<Theme> {
+    if name == DEFAULT_THEME {
+        return Ok(default_theme());
+    }
+    if let Some(theme) = BUILTIN_THEMES.themes.get(name) {
+        return Ok(theme.clone());
+    }
+
+    let path = expand_path(name);
+    if !path.exists() {
+        anyhow::bail!(
+            "`{name}` is neither a theme ({}) nor a path to a .tmTheme or .json file",
+            list().join(", ")
+        );
+    }
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tmTheme") => ThemeSet::get_theme(&path)
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display())),
+        Some("json") => load_json(&path),
+        _ => anyhow::bail!(
+            "{}: themes need to be .tmTheme or .json files",
+            path.display()
+        ),
+    }
+}
+
+fn load_json(path: &Path) -> anyhow::Result<Theme> {
+    let contents = std::fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))
+}
+
+/// Switches the theme the input is highlighted with.
+pub fn set(name: &str) -> anyhow::Result<()> {
+    *THEME.lock() = load(name)?;
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Changes whenever the theme is switched.
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::Relaxed)
+}